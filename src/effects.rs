@@ -0,0 +1,165 @@
+//! Generated 2D effects, rendered as row major images for [`crate::Strip::set_image`].
+
+use palette::{Hsv, IntoColor, Srgb};
+use smart_leds::RGB8;
+
+const TEXT_SPEED: f32 = 10.0;
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+const CALIBRATE_FLASH: f32 = 0.1;
+const CALIBRATE_COLORS: [RGB8; 4] = [
+    RGB8 { r: 255, g: 0, b: 0 },
+    RGB8 { r: 0, g: 255, b: 0 },
+    RGB8 { r: 0, g: 0, b: 255 },
+    RGB8 {
+        r: 255,
+        g: 255,
+        b: 255,
+    },
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    Plasma,
+    Noise,
+    /// Text scrolling right to left in a 3x5 font.
+    Text(String),
+    /// Flash at the start of every second, cycling red, green, blue and white
+    /// so a flash can be told apart from the previous one on a lagging target.
+    /// Every target flashes the whole frame, so film them together (at 60 fps
+    /// or more) and step through: the frames between a target's flash and
+    /// the slowest target's same coloured flash give the `delay` for it.
+    Calibrate,
+}
+
+impl Effect {
+    /// Renders the effect `t` seconds in at `width` x `height`.
+    pub fn render(&self, t: f32, width: usize, height: usize) -> Vec<RGB8> {
+        let mut image = vec![RGB8::default(); width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let (fx, fy) = (x as f32, y as f32);
+                image[y * width + x] = match self {
+                    Effect::Plasma => plasma(t, fx, fy, width, height),
+                    Effect::Noise => hue(noise(fx * 0.15, fy * 0.15, t * 0.5) * 360.0),
+                    Effect::Text(text) => self::text(text, t, x, y, width, height),
+                    Effect::Calibrate if t.fract() < CALIBRATE_FLASH => {
+                        CALIBRATE_COLORS[t as usize % CALIBRATE_COLORS.len()]
+                    }
+                    Effect::Calibrate => RGB8::default(),
+                };
+            }
+        }
+        image
+    }
+}
+
+fn hue(hue: f32) -> RGB8 {
+    let rgb: Srgb = Hsv::new(hue, 1.0, 1.0).into_color();
+    let out: Srgb<u8> = rgb.into_format();
+    RGB8::new(out.red, out.green, out.blue)
+}
+
+fn plasma(t: f32, x: f32, y: f32, width: usize, height: usize) -> RGB8 {
+    let (cx, cy) = (x - width as f32 / 2.0, y - height as f32 / 2.0);
+    let v = (x * 0.3 + t).sin()
+        + ((y * 0.3 + t) / 2.0).sin()
+        + ((x + y) * 0.2 + t).sin()
+        + ((cx * cx + cy * cy).sqrt() * 0.3 - t).sin();
+    hue(v * 45.0 + t * 20.0)
+}
+
+/// Smoothed value noise in 0..1.
+fn noise(x: f32, y: f32, z: f32) -> f32 {
+    let hash = |x: i32, y: i32, z: i32| {
+        let mut h = (x as u32).wrapping_mul(0x8da6_b343)
+            ^ (y as u32).wrapping_mul(0xd816_3841)
+            ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+        h ^= h >> 13;
+        h = h.wrapping_mul(0x5bd1_e995);
+        (h ^ (h >> 15)) as f32 / u32::MAX as f32
+    };
+    let smooth = |f: f32| f * f * (3.0 - 2.0 * f);
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+    let (ix, iy, iz) = (x.floor() as i32, y.floor() as i32, z.floor() as i32);
+    let (fx, fy, fz) = (smooth(x.fract()), smooth(y.fract()), smooth(z.fract()));
+    let plane = |iz: i32| {
+        lerp(
+            lerp(hash(ix, iy, iz), hash(ix + 1, iy, iz), fx),
+            lerp(hash(ix, iy + 1, iz), hash(ix + 1, iy + 1, iz), fx),
+            fy,
+        )
+    };
+    lerp(plane(iz), plane(iz + 1), fz)
+}
+
+fn text(text: &str, t: f32, x: usize, y: usize, width: usize, height: usize) -> RGB8 {
+    let advance = GLYPH_WIDTH + 1;
+    let top = height.saturating_sub(GLYPH_HEIGHT) / 2;
+    let scroll = (t * TEXT_SPEED) as usize % (text.chars().count() * advance + width);
+
+    let row = match y.checked_sub(top) {
+        Some(row) if row < GLYPH_HEIGHT => row,
+        _ => return RGB8::default(),
+    };
+    let col = match (x + scroll).checked_sub(width) {
+        Some(col) => col,
+        None => return RGB8::default(),
+    };
+    match text.chars().nth(col / advance) {
+        Some(c) if col % advance < GLYPH_WIDTH => {
+            let lit = glyph(c)[row] & (1 << (GLYPH_WIDTH - 1 - col % advance)) != 0;
+            RGB8::from([if lit { 255 } else { 0 }; 3])
+        }
+        _ => RGB8::default(),
+    }
+}
+
+/// Rows of a 3x5 glyph, the most significant of the low 3 bits on the left.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        '0' => [7, 5, 5, 5, 7],
+        '1' => [2, 6, 2, 2, 7],
+        '2' => [7, 1, 7, 4, 7],
+        '3' => [7, 1, 7, 1, 7],
+        '4' => [5, 5, 7, 1, 1],
+        '5' => [7, 4, 7, 1, 7],
+        '6' => [7, 4, 7, 5, 7],
+        '7' => [7, 1, 1, 1, 1],
+        '8' => [7, 5, 7, 5, 7],
+        '9' => [7, 5, 7, 1, 7],
+        'A' => [2, 5, 7, 5, 5],
+        'B' => [6, 5, 6, 5, 6],
+        'C' => [3, 4, 4, 4, 3],
+        'D' => [6, 5, 5, 5, 6],
+        'E' => [7, 4, 6, 4, 7],
+        'F' => [7, 4, 6, 4, 4],
+        'G' => [3, 4, 5, 5, 3],
+        'H' => [5, 5, 7, 5, 5],
+        'I' => [7, 2, 2, 2, 7],
+        'J' => [1, 1, 1, 5, 2],
+        'K' => [5, 5, 6, 5, 5],
+        'L' => [4, 4, 4, 4, 7],
+        'M' => [5, 7, 7, 5, 5],
+        'N' => [6, 5, 5, 5, 5],
+        'O' => [2, 5, 5, 5, 2],
+        'P' => [6, 5, 6, 4, 4],
+        'Q' => [2, 5, 5, 6, 3],
+        'R' => [6, 5, 6, 5, 5],
+        'S' => [3, 4, 2, 1, 6],
+        'T' => [7, 2, 2, 2, 2],
+        'U' => [5, 5, 5, 5, 7],
+        'V' => [5, 5, 5, 5, 2],
+        'W' => [5, 5, 7, 7, 5],
+        'X' => [5, 5, 2, 5, 5],
+        'Y' => [5, 5, 2, 2, 2],
+        'Z' => [7, 1, 2, 4, 7],
+        '!' => [2, 2, 2, 0, 2],
+        '?' => [7, 1, 2, 0, 2],
+        '.' => [0, 0, 0, 0, 2],
+        ':' => [0, 2, 0, 2, 0],
+        '-' => [0, 0, 7, 0, 0],
+        _ => [0; GLYPH_HEIGHT],
+    }
+}
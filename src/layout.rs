@@ -0,0 +1,170 @@
+use std::str::FromStr;
+
+use smart_leds::{RGB, RGB8};
+
+use crate::{Error, Result};
+
+/// Clockwise rotation of images relative to the matrix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+/// How a 2D LED matrix is wired, `width` x `height` leds in rows starting at
+/// the top left, with every other row running backwards when `serpentine`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    width: usize,
+    height: usize,
+    serpentine: bool,
+    rotation: Rotation,
+    flip_x: bool,
+    flip_y: bool,
+}
+
+impl Layout {
+    pub fn new(width: usize, height: usize) -> Self {
+        Layout {
+            width,
+            height,
+            serpentine: false,
+            rotation: Rotation::None,
+            flip_x: false,
+            flip_y: false,
+        }
+    }
+
+    pub fn serpentine(mut self) -> Self {
+        self.serpentine = true;
+        self
+    }
+
+    pub fn rotate(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn flip_x(mut self) -> Self {
+        self.flip_x = !self.flip_x;
+        self
+    }
+
+    pub fn flip_y(mut self) -> Self {
+        self.flip_y = !self.flip_y;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.width * self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Width and height of the images shown on the matrix, swapped when
+    /// rotated by a quarter turn.
+    pub fn image_size(&self) -> (usize, usize) {
+        match self.rotation {
+            Rotation::None | Rotation::Cw180 => (self.width, self.height),
+            Rotation::Cw90 | Rotation::Cw270 => (self.height, self.width),
+        }
+    }
+
+    /// Led index showing pixel `x`, `y` of an image.
+    pub fn index(&self, x: usize, y: usize) -> usize {
+        let (w, h) = self.image_size();
+        let x = if self.flip_x { w - 1 - x } else { x };
+        let y = if self.flip_y { h - 1 - y } else { y };
+        let (mx, my) = match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Cw90 => (self.width - 1 - y, x),
+            Rotation::Cw180 => (self.width - 1 - x, self.height - 1 - y),
+            Rotation::Cw270 => (y, self.height - 1 - x),
+        };
+        match self.serpentine && my % 2 == 1 {
+            true => my * self.width + self.width - 1 - mx,
+            false => my * self.width + mx,
+        }
+    }
+
+    /// Led index showing pixel `pixel` of a row major image, `None` past the
+    /// end of the image.
+    pub fn pixel_index(&self, pixel: usize) -> Option<usize> {
+        let (w, h) = self.image_size();
+        match pixel < w * h {
+            true => Some(self.index(pixel % w, pixel / w)),
+            false => None,
+        }
+    }
+
+    /// Copies a row major image onto `leds`, pixels missing from a short
+    /// image leave their leds unchanged.
+    pub fn map_image(&self, image: &[RGB8], leds: &mut [RGB<f32>]) {
+        let (w, h) = self.image_size();
+        for y in 0..h {
+            for x in 0..w {
+                if let (Some(px), Some(led)) =
+                    (image.get(y * w + x), leds.get_mut(self.index(x, y)))
+                {
+                    *led = (*px).into();
+                }
+            }
+        }
+    }
+}
+
+/// Parses a spec such as `16x16,serpentine,rot90,flipx`.
+impl FromStr for Layout {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = |part: &str| Error::ConfigError(format!("invalid layout {:?}", part));
+        let mut parts = s.split(',').map(str::trim);
+
+        let size = parts.next().unwrap_or_default();
+        let (width, height) = size.split_once('x').ok_or_else(|| invalid(size))?;
+        let layout = match (width.parse(), height.parse()) {
+            (Ok(width), Ok(height)) if width > 0 && height > 0 => Layout::new(width, height),
+            _ => return Err(invalid(size)),
+        };
+
+        parts.try_fold(layout, |layout, part| {
+            Ok(match part {
+                "serpentine" => layout.serpentine(),
+                "rot90" => layout.rotate(Rotation::Cw90),
+                "rot180" => layout.rotate(Rotation::Cw180),
+                "rot270" => layout.rotate(Rotation::Cw270),
+                "flipx" => layout.flip_x(),
+                "flipy" => layout.flip_y(),
+                _ => return Err(invalid(part)),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_follow_serpentine_rows() {
+        let layout = "3x2,serpentine".parse::<Layout>().unwrap();
+        let leds = (0..7).map(|p| layout.pixel_index(p)).collect::<Vec<_>>();
+        assert_eq!(
+            leds,
+            [Some(0), Some(1), Some(2), Some(5), Some(4), Some(3), None]
+        );
+    }
+
+    #[test]
+    fn rejects_empty_layout() {
+        for spec in ["0x4", "4x0", "0x0", "x4", "4", ""] {
+            assert!(spec.parse::<Layout>().is_err(), "{:?}", spec);
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! LED strip outputs and the frame pipeline behind the rwled daemon, usable
+//! on their own to drive the same transports from other tools.
+
+use std::net::AddrParseError;
+use thiserror::Error;
+
+pub mod effects;
+pub mod layout;
+pub mod realtime;
+pub mod strip;
+pub mod strip_transport;
+
+pub use layout::Layout;
+pub use strip::Strip;
+pub use strip_transport::{AsyncSmartLedsWrite, StripTransport};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[cfg(feature = "spi")]
+    #[error("SPI Error")]
+    SpiError(#[from] rppal::spi::Error),
+    #[error("IO Error")]
+    IOError(#[from] std::io::Error),
+    #[error("Format Error")]
+    FormatError(#[from] std::fmt::Error),
+    #[cfg(feature = "debug-image")]
+    #[error("Image Error")]
+    ImageError(#[from] image::ImageError),
+    #[error("Image Error")]
+    ParseError(#[from] AddrParseError),
+    #[error("Config Error: {0}")]
+    ConfigError(String),
+    #[error("Task Error")]
+    TaskError(#[from] tokio::task::JoinError),
+    #[cfg(feature = "hue")]
+    #[error("Reqwest Error")]
+    ReqweestError(#[from] reqwest::Error),
+    #[cfg(feature = "hue")]
+    #[error("WebRTC Error")]
+    WebRTCError(#[from] webrtc_dtls::Error),
+    #[cfg(feature = "hue")]
+    #[error("WebRTC Error")]
+    WebRTCUtilError(#[from] webrtc_util::Error),
+    #[cfg(feature = "hue")]
+    #[error("JSON Error")]
+    JsonError(#[from] serde_json::Error),
+    #[cfg(feature = "hue")]
+    #[error("Hue Error: {0}")]
+    HueError(String),
+}
@@ -1,128 +1,135 @@
-use palette::{Hsv, IntoColor, Srgb};
-use rgb::ComponentMap;
-use rgb::FromSlice;
+use rwled::effects::Effect;
+use rwled::realtime::{update_drgb, update_warls};
+use rwled::strip::fade_led;
+use rwled::strip_transport::UdpMode;
+#[cfg(feature = "hue")]
+use rwled::strip_transport::{discover_bridge, list_hue_areas, HueColorSpace, HueCredentials};
+use rwled::strip_transport::{Health, StripTransport, TransportOptions};
+use rwled::{Error, Layout, Result, Strip};
+use sd_notify::NotifyState;
 use smart_leds::{RGB, RGB8};
-use std::net::AddrParseError;
 use std::ops::Range;
 use std::pin::Pin;
-use thiserror::Error;
 use tokio::net::UdpSocket;
+use tokio::signal::unix::{signal, SignalKind};
 use tokio::time::{self, Instant, Sleep};
 
 const AVFACT_MIN: f32 = 1_f32;
 const AVFACT_MAX: f32 = 3_f32;
+#[cfg(feature = "hue")]
 const HUE_HUBIP: &str = "192.168.12.49";
-const HUE_USERNAME: &str = "";
-const HUE_CLIENTKEY: &str = "";
-
-type Result<T> = std::result::Result<T, Error>;
-
-#[derive(Error, Debug)]
-pub(crate) enum Error {
-    #[error("SPI Error")]
-    SpiError(#[from] rppal::spi::Error),
-    #[error("IO Error")]
-    IOError(#[from] std::io::Error),
-    #[error("Format Error")]
-    FormatError(#[from] std::fmt::Error),
-    #[error("Image Error")]
-    ImageError(#[from] image::ImageError),
-    #[error("Image Error")]
-    ParseError(#[from] AddrParseError),
-    #[error("Reqwest Error")]
-    ReqweestError(#[from] reqwest::Error),
-    #[error("WebRTC Error")]
-    WebRTCError(#[from] webrtc_dtls::Error),
-    #[error("WebRTC Error")]
-    WebRTCUtilError(#[from] webrtc_util::Error),
-}
-
-mod strip_transport;
-use strip_transport::StripTransport;
-
-struct Strip {
-    stream: StripTransport,
-    leds: Vec<RGB<f32>>,
-    pending: bool,
-    rainbow: f32,
-}
+#[cfg(feature = "hue")]
+const HUE_CREDENTIALS: &str = "hue-credentials.json";
+#[cfg(feature = "hue")]
+const HUE_DISCOVERY_TIMEOUT: time::Duration = time::Duration::from_secs(3);
+const OFF_SCENE: RGB8 = RGB8 { r: 0, g: 0, b: 0 };
+const OFF_SCENE_VAR: &str = "RWLED_OFF_SCENE";
+const OFF_FADE_FRAMES: u32 = 30;
+const STATUS_INTERVAL: time::Duration = time::Duration::from_secs(5);
+const LAYOUT_VAR: &str = "RWLED_LAYOUT";
+const TARGET_VAR_PREFIX: &str = "RWLED_";
 
-impl Strip {
-    fn set_led(&mut self, idx: usize, c: &[u8]) {
-        let rgb: RGB8 = c.as_rgb()[0];
-        self.leds[idx] = rgb.into();
-        self.pending = true;
-    }
-
-    async fn write(&mut self) -> Result<()> {
-        self.pending = false;
-
-        if self.rainbow != 0.0 {
-            self.stream
-                .write(self.leds.iter().enumerate().map(|(idx, led)| {
-                    let mut hsv: Hsv = Srgb::new(led.r, led.g, led.b).into_format().into_color();
-                    hsv.hue += self.rainbow * (idx as f32 / self.leds.len() as f32);
-                    let rgb: Srgb = hsv.into_color();
-                    let out: Srgb<u8> = rgb.into_format();
-                    RGB8::new(out.red, out.green, out.blue)
-                }))
-                .await
-        } else {
-            self.stream
-                .write(self.leds.iter().map(|c| c.map(|ch| ch as u8)))
-                .await
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        #[cfg(feature = "hue")]
+        Some("pair") => {
+            let hub_ip = match args.get(2) {
+                Some(ip) => ip.clone(),
+                None => hue_hub_ip(None).await?,
+            };
+            let credentials = HueCredentials::pair(&format!("https://{}", hub_ip), "rwled").await?;
+            credentials.save(HUE_CREDENTIALS)?;
+            println!("Saved credentials for {} to {}", hub_ip, HUE_CREDENTIALS);
+            return Ok(());
+        }
+        #[cfg(feature = "hue")]
+        Some("hue-areas") => {
+            let credentials = HueCredentials::load(HUE_CREDENTIALS)?;
+            let hub_ip = hue_hub_ip(credentials.bridge_id.as_deref()).await?;
+            for area in list_hue_areas(&hub_ip, &credentials).await? {
+                println!("{}", area);
+            }
+            return Ok(());
         }
+        _ => (),
     }
-}
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<()> {
-    let leds: u32 = 105;
+    let layout = match std::env::var(LAYOUT_VAR) {
+        Ok(spec) => Some(spec.parse::<Layout>()?),
+        Err(_) => None,
+    };
+    let leds: u32 = layout.map_or(105, |l| l.len() as u32);
+    let off_scene = match std::env::var(OFF_SCENE_VAR) {
+        Ok(spec) => parse_color(&spec)?,
+        Err(_) => OFF_SCENE,
+    };
 
+    #[cfg_attr(not(feature = "debug-image"), allow(unused_variables))]
     async fn transport(mode: &str, leds: u32) -> Result<StripTransport> {
-        Ok(match mode {
-            "spi" => StripTransport::ws2812()?,
-            "wled" => StripTransport::udp_str("192.168.12.76:21324")
-                .await?
-                .sample(30..75, 15),
-            "rpi" => StripTransport::udp_str("192.168.12.75:21324").await?,
-            "dbg" => StripTransport::debug_image(1024, leds as u32),
-
-            "study" => StripTransport::hue(HUE_HUBIP, HUE_USERNAME, HUE_CLIENTKEY, 7)
-                .await?
-                .sample(40..65, 1),
-
-            "bathroom" => StripTransport::hue(HUE_HUBIP, HUE_USERNAME, HUE_CLIENTKEY, 200)
-                .await?
-                .sample(30..75, 4),
-
-            "conservatory" => StripTransport::hue(HUE_HUBIP, HUE_USERNAME, HUE_CLIENTKEY, 201)
-                .await?
-                .sample(30..75, 8),
-            _ => panic!("unknown"),
-        })
+        #[cfg(feature = "hue")]
+        let hue = |group| async move {
+            let credentials = HueCredentials::load(HUE_CREDENTIALS)?;
+            StripTransport::hue(
+                &hue_hub_ip(credentials.bridge_id.as_deref()).await?,
+                &credentials,
+                group,
+                HueColorSpace::Rgb,
+            )
+            .await
+        };
+
+        let (transport, defaults): (StripTransport, &[(&str, &str)]) = match mode {
+            #[cfg(feature = "spi")]
+            "spi" => (StripTransport::ws2812()?, &[("critical", "true")]),
+            "wled" => (
+                StripTransport::udp_str("192.168.12.76:21324", UdpMode::Drgb, 5).await?,
+                &[("sample", "30..75:15")],
+            ),
+            "rpi" => (
+                StripTransport::udp_str("192.168.12.75:21324", UdpMode::Drgb, 5).await?,
+                &[],
+            ),
+            #[cfg(feature = "debug-image")]
+            "dbg" => (StripTransport::debug_image(1024, leds), &[]),
+
+            #[cfg(feature = "hue")]
+            "study" => (hue(7).await?, &[("sample", "40..65:1")]),
+            #[cfg(feature = "hue")]
+            "bathroom" => (hue(200).await?, &[("sample", "30..75:4")]),
+            #[cfg(feature = "hue")]
+            "conservatory" => (hue(201).await?, &[("sample", "30..75:8")]),
+            _ => {
+                return Err(Error::ConfigError(format!(
+                    "unknown or disabled transport {}",
+                    mode
+                )))
+            }
+        };
+        transport.configure(&target_options(mode, defaults)?)
     }
 
     let target = StripTransport::composite(vec![
+        #[cfg(feature = "spi")]
         transport("spi", leds).await?,
         transport("wled", leds).await?,
         //transport("study", leds).await?,
-    ]);
+    ])
+    .spawn();
 
     println!("Setting up strip for {:?}", target);
+    target.validate(leds as usize)?;
 
-    let mut strip = Strip {
-        stream: target,
-        leds: vec![RGB::<f32>::default(); leds as usize],
-        pending: false,
-        rainbow: 0.0,
+    let mut strip = match layout {
+        Some(layout) => Strip::with_layout(target, layout),
+        None => Strip::new(target, leds as usize),
     };
 
     let sock = UdpSocket::bind("0.0.0.0:21324").await?;
     println!("Listening on {:?}", sock.local_addr()?);
     let mut buf = [0; 490 * 3 + 2];
 
-    let mut write_interval = time::interval(time::Duration::from_secs(1));
     let mut flush_interval = time::interval(time::Duration::from_secs_f64(1.0 / 60.0));
     flush_interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
 
@@ -136,135 +143,280 @@ async fn main() -> Result<()> {
     let mut avleds = vec![<RGB<f32>>::default(); strip.leds.len()];
     let mut avfact = 0_f32;
 
-    loop {
-        tokio::select! {
-            biased;
-            _ = write_interval.tick() => strip.pending = true,
-            _ = flush_interval.tick(), if strip.pending => strip.write().await?,
-            _ = fade_interval.tick(), if audvis => {
-                audvis_tick(&mut avleds);
-                if strip.leds != avleds {
-                    strip.leds.copy_from_slice(&avleds);
-                    strip.pending = true;
-                }
-            },
-            Ok((len, _)) = sock.recv_from(&mut buf) => match &buf[..len] {
-                [mode @ 1..=2, timeout, payload @ ..] => {
-                    update_timeout(current_timeout.as_mut(), *timeout);
-
-                    match mode {
-                        1 => update_warls(&mut strip, payload),
-                        2 => update_drgb(&mut strip, payload),
-                        _ => println!("warn: unknown data mode {}", mode),
+    let mut effect: Option<Effect> = None;
+    let mut effect_start = Instant::now();
+    let mut effect_interval = time::interval(time::Duration::from_secs_f64(1.0 / 60.0));
+    effect_interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+
+    let mut sigint = signal(SignalKind::interrupt())?;
+    let mut sigterm = signal(SignalKind::terminate())?;
+
+    let mut watchdog_usec = 0;
+    let watchdog = sd_notify::watchdog_enabled(false, &mut watchdog_usec);
+    let mut watchdog_interval =
+        time::interval(time::Duration::from_micros(watchdog_usec.max(2) / 2));
+    let mut status_interval = time::interval(STATUS_INTERVAL);
+    let mut status_since = Instant::now();
+    let mut source = None;
+    // why the last "hue" command couldn't add the hue target
+    #[cfg(feature = "hue")]
+    let mut hue_error: Option<String> = None;
+
+    sd_notify::notify(false, &[NotifyState::Ready])?;
+
+    let result: Result<()> = async {
+        loop {
+            tokio::select! {
+                biased;
+                _ = sigint.recv() => break Ok(()),
+                _ = sigterm.recv() => break Ok(()),
+                _ = watchdog_interval.tick(), if watchdog => {
+                    sd_notify::notify(false, &[NotifyState::Watchdog])?;
+                },
+                _ = status_interval.tick() => {
+                    let fps = strip.frames as f64 / status_since.elapsed().as_secs_f64();
+                    let mut status = match source {
+                        Some(src) => format!("{:?} from {} at {:.1} fps", strip.stream, src, fps),
+                        None => format!("{:?} idle at {:.1} fps", strip.stream, fps),
+                    };
+                    for (kind, s) in strip.stream.health() {
+                        if s.health != Health::Healthy {
+                            status += &format!(", {} {:?} ({} errors)", kind, s.health, s.errors);
+                        }
                     }
-
-                    if audvis {
-                        audvis_process(&strip.leds, &mut avleds, &mut avfact);
-                        audvis_tick(&mut avleds);
-                        fade_interval.reset();
+                    #[cfg(feature = "hue")]
+                    if let Some(e) = &hue_error {
+                        status += &format!(", hue failed: {}", e);
+                    }
+                    sd_notify::notify(false, &[NotifyState::Status(&status)])?;
+                    strip.frames = 0;
+                    status_since = Instant::now();
+                },
+                _ = flush_interval.tick(), if strip.pending => strip.write().await?,
+                _ = fade_interval.tick(), if audvis => {
+                    audvis_tick(&mut avleds);
+                    if strip.leds != avleds {
                         strip.leds.copy_from_slice(&avleds);
+                        strip.pending = true;
                     }
                 },
-                b"warn" => {
-                    strip.leds.iter_mut()
-                        .for_each(|led| {
-                            led.r = 0.;
-                            led.g = 0.;
-                            led.b = 255.;
-                        });
-                    println!("Warn!");
-                    loop {
-                        tokio::select! {
-                            _ = flush_interval.tick(), if strip.pending => {
-                                strip.write().await?;
-                            },
-                        _ = fade_interval.tick() => {
-                                strip.leds.iter_mut()
-                        .filter(|led| led.iter().any(|f| f >= 10.) )
-                                    .for_each(|led| {
-                                        strip.pending |= fade_led(led, 0.10)
-                                    });
-                                if !strip.pending { break }
-                                strip.write().await?;
+                _ = effect_interval.tick(), if effect.is_some() => {
+                    if let Some(effect) = &effect {
+                        let (width, height) = strip.image_size();
+                        let t = effect_start.elapsed().as_secs_f32();
+                        strip.set_image(&effect.render(t, width, height));
+                    }
+                },
+                Ok((len, src)) = sock.recv_from(&mut buf) => match &buf[..len] {
+                    [mode @ 1..=2, timeout, payload @ ..] => {
+                        source = Some(src);
+                        effect = None;
+                        update_timeout(current_timeout.as_mut(), *timeout);
+
+                        match mode {
+                            1 => update_warls(&mut strip, payload),
+                            2 => update_drgb(&mut strip, payload),
+                            _ => println!("warn: unknown data mode {}", mode),
+                        }
+
+                        if audvis {
+                            audvis_process(&strip.leds, &mut avleds, &mut avfact);
+                            audvis_tick(&mut avleds);
+                            fade_interval.reset();
+                            strip.leds.copy_from_slice(&avleds);
+                        }
+                    },
+                    b"warn" => {
+                        strip.leds.iter_mut()
+                            .for_each(|led| {
+                                led.r = 0.;
+                                led.g = 0.;
+                                led.b = 255.;
+                            });
+                        println!("Warn!");
+                        loop {
+                            tokio::select! {
+                                _ = flush_interval.tick(), if strip.pending => {
+                                    strip.write().await?;
+                                },
+                            _ = fade_interval.tick() => {
+                                    strip.leds.iter_mut()
+                            .filter(|led| led.iter().any(|f| f >= 10.) )
+                                        .for_each(|led| {
+                                            strip.pending |= fade_led(led, 0.10)
+                                        });
+                                    if !strip.pending { break }
+                                    strip.write().await?;
+                                }
                             }
                         }
+                        println!("Warned!");
                     }
-                    println!("Warned!");
-                }
-                b"hue" => {
-                    if let StripTransport::Composite(mut targets) = strip.stream {
-                        let has_hue = targets.iter().any(is_hue);
-
-                        println!("targets(hue? {}): {:?}", has_hue, targets);
-                        if has_hue {
-                            targets.retain(|target| !is_hue(target));
+                    #[cfg(feature = "hue")]
+                    b"hue" => {
+                        if let StripTransport::Composite(targets) = &mut strip.stream {
+                            let has_hue = targets.iter().any(is_hue);
+
+                            println!("targets(hue? {}): {:?}", has_hue, targets);
+                            hue_error = None;
+                            if has_hue {
+                                for target in targets.iter_mut().filter(|t| is_hue(t)) {
+                                    if let Err(e) = target.close().await {
+                                        println!("WARN: closing {:?} failed: {}", target, e);
+                                    }
+                                }
+                                targets.retain(|target| !is_hue(target));
+                            } else {
+                                let added = match transport("study", 1).await {
+                                    Ok(mut target) => match target.validate(strip.leds.len()) {
+                                        Ok(()) => Ok(target.spawn()),
+                                        Err(e) => {
+                                            target.close().await.ok();
+                                            Err(e)
+                                        }
+                                    },
+                                    Err(e) => Err(e),
+                                };
+                                match added {
+                                    Ok(target) => targets.push(target),
+                                    Err(e) => {
+                                        println!("WARN: adding hue failed: {}", e);
+                                        hue_error = Some(e.to_string());
+                                    }
+                                }
+                            }
+                        }
+                        println!("-> targets: {:?}", strip.stream);
+                    }
+                    b"plasma" => {
+                        toggle_effect(&mut effect, Effect::Plasma);
+                        effect_start = Instant::now();
+                    }
+                    b"noise" => {
+                        toggle_effect(&mut effect, Effect::Noise);
+                        effect_start = Instant::now();
+                    }
+                    b"calibrate" => {
+                        toggle_effect(&mut effect, Effect::Calibrate);
+                        effect_start = Instant::now();
+                    }
+                    [b't', b'e', b'x', b't', text @ ..] => {
+                        let text = String::from_utf8_lossy(text).trim().to_string();
+                        effect = None;
+                        if !text.is_empty() {
+                            toggle_effect(&mut effect, Effect::Text(text));
+                        }
+                        effect_start = Instant::now();
+                    }
+                    b"audvis" => {
+                        audvis ^= true;
+                        if audvis { avfact = AVFACT_MIN; }
+                        println!("AudVis: {:?}", audvis);
+                    }
+                    b"rainbow" => {
+                        if strip.rainbow > 0.0 {
+                            strip.rainbow = 0.0
                         } else {
-                            targets.push(transport("study", 1).await?);
+                            strip.rainbow = 270.0
                         }
-                        strip.stream = StripTransport::composite(targets);
+                        println!("Rainbow: {:?}", strip.rainbow);
                     }
-                    println!("-> targets: {:?}", strip.stream);
-                }
-                b"audvis" => {
-                    audvis ^= true;
-                    if audvis { avfact = AVFACT_MIN; }
-                    println!("AudVis: {:?}", audvis);
-                }
-                b"rainbow" => {
-                    if strip.rainbow > 0.0 {
-                        strip.rainbow = 0.0
-                    } else {
-                        strip.rainbow = 270.0
+                    [b'r', n] => {
+                        strip.rainbow = 30.0 * (*n as f32);
+                        println!("Rainbow: {:?}", strip.rainbow);
                     }
-                    println!("Rainbow: {:?}", strip.rainbow);
-                }
-                [b'r', n] => {
-                    strip.rainbow = 30.0 * (*n as f32);
-                    println!("Rainbow: {:?}", strip.rainbow);
-                }
-                [b'r', d1 @ b'0'..=b'9', d2 @ b'0'..=b'9'] => {
-                    let n = 10 * (d1 - b'0') + (d2 - b'0');
-                    strip.rainbow = 30.0 * (n as f32);
-                    println!("Rainbow: {:?}", strip.rainbow);
-                }
-                unhandled => println!("Unhandled data: {:?}", unhandled),
-            },
-            _ = &mut current_timeout => {
-                tokio::select! {
-                    _ = fade_interval.tick() => {
-                        strip.leds.iter_mut()
-                        .filter(|led| led.iter().any(|f| f >= 0_f32) )
-                        .for_each(|led| strip.pending |= fade_led(led, 0.10));
-
-                        if !strip.pending {
-                            update_timeout(current_timeout.as_mut(), 255);
-                            avleds.copy_from_slice(&strip.leds);
+                    [b'r', d1 @ b'0'..=b'9', d2 @ b'0'..=b'9'] => {
+                        let n = 10 * (d1 - b'0') + (d2 - b'0');
+                        strip.rainbow = 30.0 * (n as f32);
+                        println!("Rainbow: {:?}", strip.rainbow);
+                    }
+                    unhandled => println!("Unhandled data: {:?}", unhandled),
+                },
+                _ = &mut current_timeout => {
+                    tokio::select! {
+                        _ = fade_interval.tick() => {
+                            strip.leds.iter_mut()
+                            .filter(|led| led.iter().any(|f| f >= 0_f32) )
+                            .for_each(|led| strip.pending |= fade_led(led, 0.10));
+
+                            if !strip.pending {
+                                update_timeout(current_timeout.as_mut(), 255);
+                                avleds.copy_from_slice(&strip.leds);
+                                source = None;
+                            }
                         }
                     }
-                }
-            },
+                },
+            }
         }
     }
+    .await;
+
+    println!("Shutting down {:?}", strip.stream);
+    sd_notify::notify(false, &[NotifyState::Stopping])?;
+    let faded = match result {
+        Ok(()) => strip.fade_out(off_scene.into(), OFF_FADE_FRAMES).await,
+        Err(e) => Err(e),
+    };
+    let closed = strip.stream.close().await;
+    faded.and(closed)
+}
+
+#[cfg(feature = "hue")]
+async fn hue_hub_ip(bridge_id: Option<&str>) -> Result<String> {
+    let found = discover_bridge(bridge_id, HUE_DISCOVERY_TIMEOUT).await?;
+    Ok(match found {
+        Some((_, ip)) => ip.to_string(),
+        None => {
+            println!("warn: no hue bridge found, using {}", HUE_HUBIP);
+            String::from(HUE_HUBIP)
+        }
+    })
 }
 
+#[cfg(feature = "hue")]
 fn is_hue(target: &StripTransport) -> bool {
-    if let StripTransport::Sampled(s) = target {
-        matches!(s.base.as_ref(), StripTransport::Hue(_))
-    } else {
-        matches!(target, StripTransport::Hue(_))
+    target.kind() == Some("hue")
+}
+
+/// Options for target `name`, see [`StripTransport::configure`]. `defaults`
+/// are overridden by `RWLED_<NAME>`, `key=value` pairs separated by `;` such
+/// as `max-fps=40;delay=0.1;resample=linear`.
+fn target_options(name: &str, defaults: &[(&str, &str)]) -> Result<TransportOptions> {
+    let mut options: TransportOptions = defaults
+        .iter()
+        .map(|(key, value)| (String::from(*key), String::from(*value)))
+        .collect();
+    let var = format!("{}{}", TARGET_VAR_PREFIX, name.to_uppercase());
+    if let Ok(spec) = std::env::var(&var) {
+        for pair in spec.split(';').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| Error::ConfigError(format!("invalid {} option {:?}", var, pair)))?;
+            options.insert(String::from(key.trim()), String::from(value.trim()));
+        }
     }
+    Ok(options)
 }
 
-fn fade_led(led: &mut RGB<f32>, factor: f32) -> bool {
-    if factor <= 0.0 {
-        return false;
+/// Parses a `rrggbb` hex colour, optionally prefixed with `#`.
+fn parse_color(spec: &str) -> Result<RGB8> {
+    let hex = spec.trim().trim_start_matches('#');
+    match u32::from_str_radix(hex, 16) {
+        Ok(rgb) if hex.len() == 6 => Ok(RGB8::new((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)),
+        _ => Err(Error::ConfigError(format!(
+            "invalid colour {:?}, expected rrggbb",
+            spec
+        ))),
     }
-    let f = led.iter().reduce(f32::max).unwrap_or_default() * factor;
+}
 
-    let updated = led.map(|ch| 0.001_f32.max(ch - f));
-    let changed = updated != *led;
-    *led = updated;
-    changed
+fn toggle_effect(effect: &mut Option<Effect>, requested: Effect) {
+    *effect = match effect.take() {
+        Some(current) if current == requested => None,
+        _ => Some(requested),
+    };
+    println!("Effect: {:?}", effect);
 }
 
 fn update_timeout(timeout: Pin<&mut Sleep>, duration_secs: u8) {
@@ -274,21 +426,6 @@ fn update_timeout(timeout: Pin<&mut Sleep>, duration_secs: u8) {
     };
 }
 
-fn update_warls(strip: &mut Strip, buf: &[u8]) {
-    buf.chunks(4).for_each(|c| match c[0] as usize {
-        i if i < strip.leds.len() => strip.set_led(i, &c[1..]),
-        _ => (),
-    });
-}
-
-fn update_drgb(strip: &mut Strip, buf: &[u8]) {
-    strip.pending = true;
-    buf.chunks(3)
-        .take(strip.leds.len())
-        .enumerate()
-        .for_each(|(i, c)| strip.set_led(i, c));
-}
-
 fn ratio_range(range: Range<f32>, length: usize) -> Range<usize> {
     let bound = |f: f32| {
         if f <= 0.0 {
@@ -310,9 +447,9 @@ fn audvis_process(rawleds: &[RGB<f32>], avleds: &mut [RGB<f32>], avfact: &mut f3
     };
 
     let ctr = <RGB<f32>>::new(
-        chval(&rawleds, 0.0..0.15) * 1.2 * *avfact,
-        chval(&rawleds, 0.25..0.50) * 0.9 * *avfact,
-        chval(&rawleds, 0.50..1.0) * *avfact,
+        chval(rawleds, 0.0..0.15) * 1.2 * *avfact,
+        chval(rawleds, 0.25..0.50) * 0.9 * *avfact,
+        chval(rawleds, 0.50..1.0) * *avfact,
     );
 
     *avfact += 0.001;
@@ -325,7 +462,7 @@ fn audvis_process(rawleds: &[RGB<f32>], avleds: &mut [RGB<f32>], avfact: &mut f3
 fn audvis_tick(avleds: &mut [RGB<f32>]) {
     let l = avleds.len();
     for idx in 0..l / 2 {
-        avleds[idx] = avleds[idx + 1].clone();
+        avleds[idx] = avleds[idx + 1];
         fade_led(&mut avleds[idx], 1.0 / (l) as f32);
         avleds[avleds.len() - idx - 1] = avleds[idx];
     }
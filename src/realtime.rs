@@ -0,0 +1,21 @@
+//! Parsers for the WLED realtime UDP payloads rwled listens for.
+
+use smart_leds::RGB8;
+
+use crate::Strip;
+
+/// WARLS packets are index, r, g, b quadruples, a trailing partial one is
+/// ignored. Indices address image pixels, so they follow the layout too.
+pub fn update_warls(strip: &mut Strip, buf: &[u8]) {
+    buf.chunks_exact(4)
+        .for_each(|c| strip.set_led(c[0] as usize, &c[1..]));
+}
+
+/// DRGB frames are treated as images, so they follow the strip's layout.
+pub fn update_drgb(strip: &mut Strip, buf: &[u8]) {
+    let image = buf
+        .chunks_exact(3)
+        .map(|c| RGB8::new(c[0], c[1], c[2]))
+        .collect::<Vec<_>>();
+    strip.set_image(&image);
+}
@@ -0,0 +1,131 @@
+use palette::{Hsv, IntoColor, Srgb};
+use rgb::ComponentMap;
+use rgb::FromSlice;
+use smart_leds::{RGB, RGB8};
+use tokio::time;
+
+use crate::strip_transport::StripTransport;
+use crate::{Layout, Result};
+
+/// The logical frame, written out through `stream` whenever `pending`.
+pub struct Strip {
+    pub stream: StripTransport,
+    pub leds: Vec<RGB<f32>>,
+    pub pending: bool,
+    pub rainbow: f32,
+    pub frames: u32,
+    /// Wiring of a matrix, images are mapped through it onto `leds`.
+    pub layout: Option<Layout>,
+}
+
+impl Strip {
+    pub fn new(stream: StripTransport, leds: usize) -> Self {
+        Strip {
+            stream,
+            leds: vec![RGB::<f32>::default(); leds],
+            pending: false,
+            rainbow: 0.0,
+            frames: 0,
+            layout: None,
+        }
+    }
+
+    /// Sets up the strip as a matrix wired as `layout`, sized to fit it.
+    pub fn with_layout(stream: StripTransport, layout: Layout) -> Self {
+        Strip {
+            layout: Some(layout),
+            ..Self::new(stream, layout.len())
+        }
+    }
+
+    /// Width and height of images for [`Strip::set_image`], a single row
+    /// covering every led without a layout.
+    pub fn image_size(&self) -> (usize, usize) {
+        match &self.layout {
+            Some(layout) => layout.image_size(),
+            None => (self.leds.len(), 1),
+        }
+    }
+
+    pub fn set_image(&mut self, image: &[RGB8]) {
+        match &self.layout {
+            Some(layout) => layout.map_image(image, &mut self.leds),
+            None => self
+                .leds
+                .iter_mut()
+                .zip(image)
+                .for_each(|(led, px)| *led = (*px).into()),
+        }
+        self.pending = true;
+    }
+
+    /// Sets pixel `idx` from the first r, g, b triple of `c`, ignoring a
+    /// short slice or an index past the end of the strip. Like images, pixels
+    /// are mapped through the layout.
+    pub fn set_led(&mut self, idx: usize, c: &[u8]) {
+        let idx = match &self.layout {
+            Some(layout) => layout.pixel_index(idx),
+            None => Some(idx),
+        };
+        let led = idx.and_then(|i| self.leds.get_mut(i));
+        if let (Some(rgb), Some(led)) = (c.as_rgb().first(), led) {
+            *led = (*rgb).into();
+            self.pending = true;
+        }
+    }
+
+    pub async fn write(&mut self) -> Result<()> {
+        self.pending = false;
+        self.frames += 1;
+
+        if self.rainbow != 0.0 {
+            self.stream
+                .write(self.leds.iter().enumerate().map(|(idx, led)| {
+                    let mut hsv: Hsv = Srgb::new(led.r, led.g, led.b).into_format().into_color();
+                    hsv.hue += self.rainbow * (idx as f32 / self.leds.len() as f32);
+                    let rgb: Srgb = hsv.into_color();
+                    let out: Srgb<u8> = rgb.into_format();
+                    RGB8::new(out.red, out.green, out.blue)
+                }))
+                .await
+        } else {
+            self.stream
+                .write(self.leds.iter().map(|c| c.map(|ch| ch as u8)))
+                .await
+        }
+    }
+
+    /// Crossfades from the current frame to `scene` over `frames` frames at 60 fps.
+    pub async fn fade_out(&mut self, scene: RGB<f32>, frames: u32) -> Result<()> {
+        let from = self.leds.clone();
+        let mut interval = time::interval(time::Duration::from_secs_f64(1.0 / 60.0));
+        for frame in 1..=frames {
+            interval.tick().await;
+            let t = frame as f32 / frames as f32;
+            self.leds
+                .iter_mut()
+                .zip(from.iter())
+                .for_each(|(led, from)| {
+                    *led = RGB::new(
+                        from.r + (scene.r - from.r) * t,
+                        from.g + (scene.g - from.g) * t,
+                        from.b + (scene.b - from.b) * t,
+                    )
+                });
+            self.write().await?;
+        }
+        Ok(())
+    }
+}
+
+pub fn fade_led(led: &mut RGB<f32>, factor: f32) -> bool {
+    if factor <= 0.0 {
+        return false;
+    }
+    let f = led.iter().reduce(f32::max).unwrap_or_default() * factor;
+
+    let updated = led.map(|ch| 0.001_f32.max(ch - f));
+    let changed = updated != *led;
+    *led = updated;
+    changed
+}
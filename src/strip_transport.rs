@@ -1,189 +1,357 @@
-use super::Result;
+use crate::{Error, Result};
 
 use async_trait::async_trait;
-use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
-use smart_leds::{SmartLedsWrite, RGB8};
+use futures::future::BoxFuture;
+use smart_leds::RGB8;
 use std::ops::Range;
 use std::str::FromStr;
-use ws2812_spi::hosted::Ws2812;
 
+#[cfg(feature = "debug-image")]
 mod dbgimg;
+mod ddp;
+mod health;
+#[cfg(feature = "hue")]
 mod huee;
+mod mapping;
+mod opc;
+mod registry;
+mod resample;
+#[cfg(feature = "spi")]
+mod spi;
+mod task;
 mod udpstrip;
 
-pub(super) enum StripTransport {
-    Ws2812(Ws2812<Spi>),
-    Hue(huee::Hue),
-    Udp(udpstrip::UdpStrip),
-    DebugImage(dbgimg::DebugImage),
+pub use health::{Health, HealthMonitor, HealthPolicy, HealthStatus};
+#[cfg(feature = "hue")]
+pub use huee::{
+    discover_bridge, list_areas as list_hue_areas, Hue, HueArea, HueAreaInfo, HueColorSpace,
+    HueCredentials, HueLightInfo,
+};
+pub use mapping::{MappedStripTransport, Mapping, Segment};
+pub use registry::{TransportFactory, TransportOptions, TransportRegistry};
+pub use resample::{scale, Resample};
+pub use task::{Refresh, SinkTask};
+pub use udpstrip::UdpMode;
+
+/// A tree of outputs, leaves are any [`FrameSink`].
+pub enum StripTransport {
+    Sink(Box<dyn FrameSink>),
     Composite(Vec<StripTransport>),
     Sampled(SampledStripTransport),
+    Mapped(MappedStripTransport),
 }
 
 impl std::fmt::Debug for StripTransport {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            StripTransport::Ws2812(_) => f.write_str("ws2812"),
-            StripTransport::Hue(h) => f.write_str(format!("hue:{}", h.desc).as_str()),
-            StripTransport::Udp(u) => f.write_str(format!("udp:{:?}", u.dest).as_str()),
-            StripTransport::DebugImage(_) => f.write_str("dbg"),
+            StripTransport::Sink(s) => f.write_str(format!("{:?}", s).as_str()),
             StripTransport::Composite(c) => f.write_str(format!("({:?})", c).as_str()),
             StripTransport::Sampled(s) => {
                 f.write_str(format!("{:?}[{:?}:{:?}]", s.base, s.range, s.count).as_str())
             }
+            StripTransport::Mapped(m) => {
+                f.write_str(format!("{:?}{:?}", m.base, m.mapping).as_str())
+            }
         }
     }
 }
 
-pub(crate) struct SampledStripTransport {
-    pub(crate) base: Box<StripTransport>,
+pub struct SampledStripTransport {
+    pub base: Box<StripTransport>,
     range: Range<usize>,
     count: usize,
+    resample: Resample,
 }
 
 impl SampledStripTransport {
-    async fn write<T, I>(&mut self, iterator: T) -> Result<()>
-    where
-        T: Iterator<Item = I>,
-        I: Into<RGB8>,
-    {
+    async fn write(&mut self, frame: &[RGB8]) -> Result<()> {
         let r = self.range.start..self.range.end;
-        let scaled = scale(iterator, r, self.count);
-        self.base
-            .write_base(scaled.iter().map(|ch| ch.map(|c| c as u8)))
-            .await
+        let scaled = scale(frame.iter().copied(), r, self.count, self.resample)
+            .into_iter()
+            .map(RGB8::from)
+            .collect::<Vec<_>>();
+        self.base.write_frame(&scaled).await
     }
 }
 
 #[allow(dead_code)]
 impl StripTransport {
-    pub(crate) fn ws2812() -> Result<Self> {
-        let spi = Spi::new(Bus::Spi0, SlaveSelect::Ss0, 3_000_000, Mode::Mode0)?;
-        Ok(Self::Ws2812(Ws2812::new(spi)))
+    pub fn sink(sink: impl FrameSink + 'static) -> Self {
+        Self::Sink(Box::new(sink))
     }
 
-    pub(crate) async fn hue(
+    #[cfg(feature = "spi")]
+    pub fn ws2812() -> Result<Self> {
+        Ok(Self::sink(spi::Ws2812Strip::new()?))
+    }
+
+    #[cfg(feature = "hue")]
+    pub async fn hue(
         hub_ip: &str,
-        username: &str,
-        clientkey: &str,
+        credentials: &HueCredentials,
         group: u16,
+        color_space: HueColorSpace,
+    ) -> Result<Self> {
+        Ok(Self::sink(
+            Hue::new(hub_ip, credentials, HueArea::Group(group), color_space).await?,
+        ))
+    }
+
+    #[cfg(feature = "hue")]
+    pub async fn hue_v2(
+        hub_ip: &str,
+        credentials: &HueCredentials,
+        entertainment_configuration: &str,
+        color_space: HueColorSpace,
     ) -> Result<Self> {
-        Ok(Self::Hue(
-            huee::Hue::new(hub_ip, username, clientkey, group).await?,
+        Ok(Self::sink(
+            Hue::new(
+                hub_ip,
+                credentials,
+                HueArea::Entertainment(String::from(entertainment_configuration)),
+                color_space,
+            )
+            .await?,
+        ))
+    }
+
+    pub async fn udp(dest: std::net::SocketAddr, mode: UdpMode, timeout: u8) -> Result<Self> {
+        Ok(Self::sink(
+            udpstrip::UdpStrip::new(dest, mode, timeout).await?,
         ))
     }
 
-    pub(crate) async fn udp(dest: std::net::SocketAddr) -> Result<Self> {
-        Ok(Self::Udp(udpstrip::UdpStrip::new(dest).await?))
+    pub async fn udp_str(dest: &str, mode: UdpMode, timeout: u8) -> Result<Self> {
+        Self::udp(
+            std::net::SocketAddr::V4(std::net::SocketAddrV4::from_str(dest)?),
+            mode,
+            timeout,
+        )
+        .await
     }
 
-    pub(crate) async fn udp_str(dest: &str) -> Result<Self> {
-        Self::udp(std::net::SocketAddr::V4(std::net::SocketAddrV4::from_str(
+    pub async fn ddp(dest: std::net::SocketAddr) -> Result<Self> {
+        Ok(Self::sink(ddp::DdpStrip::new(dest).await?))
+    }
+
+    pub async fn ddp_str(dest: &str) -> Result<Self> {
+        Self::ddp(std::net::SocketAddr::V4(std::net::SocketAddrV4::from_str(
             dest,
         )?))
         .await
     }
 
-    pub(crate) fn debug_image(width: u32, height: u32) -> Self {
-        Self::DebugImage(dbgimg::DebugImage::new(width, height))
+    pub fn opc(dest: std::net::SocketAddr, channel: u8) -> Self {
+        Self::sink(opc::OpcStrip::new(dest, channel))
+    }
+
+    pub fn opc_str(dest: &str, channel: u8) -> Result<Self> {
+        Ok(Self::opc(
+            std::net::SocketAddr::V4(std::net::SocketAddrV4::from_str(dest)?),
+            channel,
+        ))
+    }
+
+    #[cfg(feature = "debug-image")]
+    pub fn debug_image(width: u32, height: u32) -> Self {
+        Self::sink(dbgimg::DebugImage::new(width, height))
     }
 
     pub async fn write<T, I>(&mut self, iterator: T) -> Result<()>
     where
-        T: Iterator<Item = I> + Send + Clone,
+        T: Iterator<Item = I>,
         I: Into<RGB8>,
     {
+        let frame = iterator.map(|item| item.into()).collect::<Vec<_>>();
+        self.write_frame(&frame).await
+    }
+
+    pub fn write_frame<'a>(&'a mut self, frame: &'a [RGB8]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            match self {
+                StripTransport::Sink(s) => s.write_frame(frame).await?,
+                StripTransport::Composite(s) => {
+                    futures::future::join_all(s.iter_mut().map(|t| t.write_frame(frame)))
+                        .await
+                        .into_iter()
+                        .collect::<Result<Vec<_>>>()?;
+                }
+                StripTransport::Sampled(s) => s.write(frame).await?,
+                StripTransport::Mapped(m) => m.write(frame).await?,
+            }
+            Ok(())
+        })
+    }
+
+    /// Closes every output in the tree, continuing past failures so one
+    /// target can't leave the others active.
+    pub fn close(&mut self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            match self {
+                StripTransport::Sink(s) => s.close().await?,
+                StripTransport::Composite(s) => {
+                    futures::future::join_all(s.iter_mut().map(|t| t.close()))
+                        .await
+                        .into_iter()
+                        .collect::<Result<Vec<_>>>()?;
+                }
+                StripTransport::Sampled(s) => s.base.close().await?,
+                StripTransport::Mapped(m) => m.base.close().await?,
+            }
+            Ok(())
+        })
+    }
+
+    /// Registry name of the output behind a (possibly sampled) leaf.
+    pub fn kind(&self) -> Option<&'static str> {
+        match self {
+            StripTransport::Sink(s) => Some(s.kind()),
+            StripTransport::Composite(_) => None,
+            StripTransport::Sampled(s) => s.base.kind(),
+            StripTransport::Mapped(m) => m.base.kind(),
+        }
+    }
+
+    /// Moves every leaf not spawned yet into its own task, see [`SinkTask`].
+    /// A task reads the leaf's [`Refresh`] when it starts, so any
+    /// [`with_refresh`](Self::with_refresh) has to come before this.
+    pub fn spawn(self) -> Self {
         match self {
-            StripTransport::Composite(s) => {
-                futures::future::try_join_all(
-                    s.iter_mut().map(|t| t.write_single(iterator.clone())),
-                )
-                .await?;
+            StripTransport::Sink(s) if s.spawned() => Self::Sink(s),
+            StripTransport::Sink(s) => Self::sink(SinkTask::spawn(s)),
+            StripTransport::Composite(c) => {
+                Self::Composite(c.into_iter().map(Self::spawn).collect())
+            }
+            StripTransport::Sampled(mut s) => {
+                s.base = Box::new(s.base.spawn());
+                Self::Sampled(s)
+            }
+            StripTransport::Mapped(mut m) => {
+                m.base = Box::new(m.base.spawn());
+                Self::Mapped(m)
             }
-            _ => self.write_single(iterator).await?,
         }
-        Ok(())
     }
 
-    async fn write_base<T, I>(&mut self, iterator: T) -> Result<()>
-    where
-        T: Iterator<Item = I> + Send + Clone,
-        I: Into<RGB8>,
-    {
+    /// Overrides the [`Refresh`] of every leaf, for fields set in `refresh`.
+    /// Fails if a leaf has already been [spawned](Self::spawn), as its task
+    /// would never see the override.
+    pub fn with_refresh(self, refresh: Refresh) -> Result<Self> {
+        Ok(match self {
+            StripTransport::Sink(sink) if sink.spawned() => {
+                return Err(Error::ConfigError(format!(
+                    "{:?} is already spawned, set its refresh before spawn",
+                    sink
+                )))
+            }
+            StripTransport::Sink(sink) => Self::sink(task::Refreshed { sink, refresh }),
+            StripTransport::Composite(c) => Self::Composite(
+                c.into_iter()
+                    .map(|t| t.with_refresh(refresh))
+                    .collect::<Result<_>>()?,
+            ),
+            StripTransport::Sampled(mut s) => {
+                s.base = Box::new(s.base.with_refresh(refresh)?);
+                Self::Sampled(s)
+            }
+            StripTransport::Mapped(mut m) => {
+                m.base = Box::new(m.base.with_refresh(refresh)?);
+                Self::Mapped(m)
+            }
+        })
+    }
+
+    /// Wraps every leaf not guarded yet so its write errors are handled by
+    /// `policy` instead of failing the whole tree.
+    pub fn guard(self, policy: HealthPolicy) -> Self {
         match self {
-            StripTransport::Ws2812(s) => s.write(iterator)?,
-            StripTransport::Hue(s) => s.write(iterator).await?,
-            StripTransport::Udp(s) => s.write(iterator).await?,
-            StripTransport::DebugImage(i) => i.write(iterator.map(|f| {
-                let i = f.into();
-                [i.r, i.g, i.b]
-            }))?,
-            _ => panic!("nope {:?}", self),
+            StripTransport::Sink(sink) if sink.monitor().is_some() => Self::Sink(sink),
+            StripTransport::Sink(sink) => Self::sink(health::Guarded::new(sink, policy)),
+            StripTransport::Composite(c) => {
+                Self::Composite(c.into_iter().map(|t| t.guard(policy)).collect())
+            }
+            StripTransport::Sampled(mut s) => {
+                s.base = Box::new(s.base.guard(policy));
+                Self::Sampled(s)
+            }
+            StripTransport::Mapped(mut m) => {
+                m.base = Box::new(m.base.guard(policy));
+                Self::Mapped(m)
+            }
         }
-        Ok(())
     }
 
-    async fn write_single<T, I>(&mut self, iterator: T) -> Result<()>
-    where
-        T: Iterator<Item = I> + Send + Clone,
-        I: Into<RGB8>,
-    {
+    /// Kind and status of every guarded leaf.
+    pub fn health(&self) -> Vec<(&'static str, HealthStatus)> {
         match self {
-            StripTransport::Sampled(s) => s.write(iterator.map(|x| x.into())).await?,
-            _ => self.write_base(iterator).await?,
+            StripTransport::Sink(s) => s
+                .monitor()
+                .map(|monitor| (s.kind(), monitor.status()))
+                .into_iter()
+                .collect(),
+            StripTransport::Composite(c) => c.iter().flat_map(Self::health).collect(),
+            StripTransport::Sampled(s) => s.base.health(),
+            StripTransport::Mapped(m) => m.base.health(),
         }
-        Ok(())
     }
 
-    pub(crate) fn composite(transports: Vec<StripTransport>) -> Self {
+    pub fn composite(transports: Vec<StripTransport>) -> Self {
         StripTransport::Composite(transports)
     }
 
-    pub(crate) fn sample(self, range: Range<usize>, count: usize) -> Self {
+    /// Maps `range` of the frame onto `count` leds of this transport, which
+    /// may itself be sampled or composite.
+    pub fn sample(self, range: Range<usize>, count: usize) -> Result<Self> {
+        self.sample_with(range, count, Resample::default())
+    }
+
+    pub fn sample_with(
+        self,
+        range: Range<usize>,
+        count: usize,
+        resample: Resample,
+    ) -> Result<Self> {
+        if range.is_empty() || count == 0 {
+            return Err(Error::ConfigError(format!(
+                "cannot sample {:?} onto {} leds of {:?}",
+                range, count, self
+            )));
+        }
+        Ok(Self::Sampled(SampledStripTransport {
+            base: Box::new(self),
+            range,
+            count,
+            resample,
+        }))
+    }
+
+    /// Checks every sampled range in the tree lies within the frame it is
+    /// given, `leds` being the length of frames written to this transport.
+    pub fn validate(&self, leds: usize) -> Result<()> {
         match self {
-            StripTransport::Composite(_) => panic!("Cannot nest composite transport in sampled"),
-            StripTransport::Sampled(_) => panic!("Cannot nest sampled transport in sampled"),
-            _ => Self::Sampled(SampledStripTransport {
-                base: Box::new(self),
-                range,
-                count,
-            }),
+            StripTransport::Sink(_) => Ok(()),
+            StripTransport::Composite(c) => c.iter().try_for_each(|t| t.validate(leds)),
+            StripTransport::Sampled(s) if s.range.end > leds => Err(Error::ConfigError(format!(
+                "{:?} samples past the end of a {} led frame",
+                self, leds
+            ))),
+            StripTransport::Sampled(s) => s.base.validate(s.count),
+            StripTransport::Mapped(m) if m.source_len() > leds => Err(Error::ConfigError(format!(
+                "{:?} maps past the end of a {} led frame",
+                self, leds
+            ))),
+            StripTransport::Mapped(m) => m.base.validate(m.len()),
         }
     }
-}
 
-fn scale<T, I>(iterator: T, range: Range<usize>, leds: usize) -> Vec<[u8; 3]>
-where
-    T: Iterator<Item = I>,
-    I: Into<RGB8>,
-{
-    let src = iterator
-        .skip(range.start)
-        .map(|x| {
-            let i = x.into();
-            [i.r as f32, i.g as f32, i.b as f32]
-        })
-        .collect::<Vec<_>>();
-    let scaled = src
-        .chunks(range.len() / leds)
-        .take(leds)
-        .map(|chunk| {
-            chunk
-                .iter()
-                .fold([0_f32; 3], |mut out, v| {
-                    out[0] += v[0];
-                    out[1] += v[1];
-                    out[2] += v[2];
-                    out
-                })
-                .map(|c| (c / chunk.len() as f32) as u8)
-        })
-        .collect::<Vec<_>>();
-    scaled
+    /// Drives this transport's leds from the frame as described by `mapping`.
+    pub fn map(self, mapping: Mapping) -> Result<Self> {
+        Ok(Self::Mapped(MappedStripTransport::new(self, mapping)?))
+    }
 }
 
 #[async_trait]
-pub(crate) trait AsyncSmartLedsWrite {
+pub trait AsyncSmartLedsWrite {
     type Error;
     type Color;
     async fn write<T, I>(&mut self, iterator: T) -> std::result::Result<(), Self::Error>
@@ -191,4 +359,39 @@ pub(crate) trait AsyncSmartLedsWrite {
         T: Iterator<Item = I>,
         T: Send,
         I: Into<Self::Color>;
+
+    /// Releases the output, e.g. handing a streaming session back to its
+    /// device. Nothing should be written after closing.
+    async fn close(&mut self) -> std::result::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Object-safe counterpart of [`AsyncSmartLedsWrite`], implement this to add
+/// an output without touching [`StripTransport`].
+#[async_trait]
+pub trait FrameSink: Send + std::fmt::Debug {
+    /// Name the transport is registered under, e.g. `"udp"`.
+    fn kind(&self) -> &'static str;
+
+    /// Rate limit and keepalive suited to the device, used once spawned.
+    fn refresh(&self) -> Refresh {
+        Refresh::default()
+    }
+
+    /// Whether the sink already runs in a [`SinkTask`].
+    fn spawned(&self) -> bool {
+        false
+    }
+
+    /// Health of the sink if it is guarded, see [`StripTransport::guard`].
+    fn monitor(&self) -> Option<HealthMonitor> {
+        None
+    }
+
+    async fn write_frame(&mut self, frame: &[RGB8]) -> Result<()>;
+
+    async fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
 }
@@ -1,5 +1,9 @@
 use image::{ImageError, ImageResult, Rgb};
-use smart_leds::SmartLedsWrite;
+use smart_leds::{SmartLedsWrite, RGB8};
+
+use super::FrameSink;
+use crate::Result;
+use async_trait::async_trait;
 
 pub struct DebugImage {
     img: image::RgbImage,
@@ -40,3 +44,21 @@ impl SmartLedsWrite for DebugImage {
         Ok(())
     }
 }
+
+impl std::fmt::Debug for DebugImage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("dbg")
+    }
+}
+
+#[async_trait]
+impl FrameSink for DebugImage {
+    fn kind(&self) -> &'static str {
+        "debug-image"
+    }
+
+    async fn write_frame(&mut self, frame: &[RGB8]) -> Result<()> {
+        SmartLedsWrite::write(self, frame.iter().map(|i| [i.r, i.g, i.b]))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+use std::net::SocketAddr;
+
+use rgb::RGB8;
+use tokio::net::UdpSocket;
+
+use super::{AsyncSmartLedsWrite, FrameSink};
+use crate::{Error, Result};
+use async_trait::async_trait;
+
+const DDP_HEADER_LEN: usize = 10;
+const DDP_MAX_DATA_LEN: usize = 480 * 3;
+
+const DDP_FLAGS_VER1: u8 = 0x40;
+const DDP_FLAGS_PUSH: u8 = 0x01;
+const DDP_TYPE_RGB24: u8 = 0x0B;
+const DDP_ID_DISPLAY: u8 = 0x01;
+
+pub struct DdpStrip {
+    pub(crate) dest: SocketAddr,
+    sock: UdpSocket,
+    sequence: u8,
+    pixels: Vec<u8>,
+    buf: [u8; DDP_HEADER_LEN + DDP_MAX_DATA_LEN],
+}
+
+impl DdpStrip {
+    pub(crate) async fn new(dest: std::net::SocketAddr) -> Result<Self> {
+        let sock = tokio::net::UdpSocket::bind("0.0.0.0:0").await?;
+        Ok(DdpStrip {
+            sock,
+            dest,
+            sequence: 0,
+            pixels: Vec::new(),
+            buf: [u8::default(); DDP_HEADER_LEN + DDP_MAX_DATA_LEN],
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncSmartLedsWrite for DdpStrip {
+    type Error = Error;
+    type Color = RGB8;
+
+    async fn write<T, I>(&mut self, iterator: T) -> Result<()>
+    where
+        T: Iterator<Item = I> + Send,
+        I: Into<Self::Color>,
+    {
+        self.pixels.clear();
+        self.pixels.extend(iterator.flat_map(|item| {
+            let i = item.into();
+            [i.r, i.g, i.b]
+        }));
+
+        // sequence numbers run 1..=15, 0 tells the receiver not to track them
+        self.sequence = self.sequence % 15 + 1;
+
+        let chunks = self.pixels.chunks(DDP_MAX_DATA_LEN);
+        let last = chunks.len().saturating_sub(1);
+        for (n, chunk) in chunks.enumerate() {
+            let offset = (n * DDP_MAX_DATA_LEN) as u32;
+            let len = chunk.len() as u16;
+
+            self.buf[0] = DDP_FLAGS_VER1 | if n == last { DDP_FLAGS_PUSH } else { 0 };
+            self.buf[1] = self.sequence;
+            self.buf[2] = DDP_TYPE_RGB24;
+            self.buf[3] = DDP_ID_DISPLAY;
+            self.buf[4..8].copy_from_slice(&offset.to_be_bytes());
+            self.buf[8..10].copy_from_slice(&len.to_be_bytes());
+            self.buf[DDP_HEADER_LEN..DDP_HEADER_LEN + chunk.len()].copy_from_slice(chunk);
+
+            self.sock
+                .send_to(&self.buf[..DDP_HEADER_LEN + chunk.len()], self.dest)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for DdpStrip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ddp:{:?}", self.dest)
+    }
+}
+
+#[async_trait]
+impl FrameSink for DdpStrip {
+    fn kind(&self) -> &'static str {
+        "ddp"
+    }
+
+    async fn write_frame(&mut self, frame: &[RGB8]) -> Result<()> {
+        AsyncSmartLedsWrite::write(self, frame.iter().copied()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rgb::RGB8;
+    use tokio::net::UdpSocket;
+
+    use super::*;
+
+    async fn receive(sock: &UdpSocket, packets: usize) -> Vec<Vec<u8>> {
+        let mut buf = [0; 2048];
+        let mut received = Vec::new();
+        for _ in 0..packets {
+            let len = sock.recv(&mut buf).await.unwrap();
+            received.push(buf[..len].to_vec());
+        }
+        received
+    }
+
+    #[tokio::test]
+    async fn fragments_at_max_payload() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut ddp = DdpStrip::new(receiver.local_addr().unwrap()).await.unwrap();
+        let frame = (0..1000)
+            .map(|i| RGB8::new(i as u8, (i >> 8) as u8, 7))
+            .collect::<Vec<_>>();
+        ddp.write_frame(&frame).await.unwrap();
+
+        let packets = receive(&receiver, 3).await;
+        let lens = [
+            DDP_MAX_DATA_LEN,
+            DDP_MAX_DATA_LEN,
+            3000 - 2 * DDP_MAX_DATA_LEN,
+        ];
+        for (n, (packet, len)) in packets.iter().zip(lens).enumerate() {
+            let push = if n == 2 { DDP_FLAGS_PUSH } else { 0 };
+            assert_eq!(packet[0], DDP_FLAGS_VER1 | push);
+            assert_eq!(packet[1], 1);
+            assert_eq!(packet[2], DDP_TYPE_RGB24);
+            assert_eq!(packet[3], DDP_ID_DISPLAY);
+            let offset = (n * DDP_MAX_DATA_LEN) as u32;
+            assert_eq!(packet[4..8], offset.to_be_bytes());
+            assert_eq!(packet[8..10], (len as u16).to_be_bytes());
+            assert_eq!(packet.len(), DDP_HEADER_LEN + len);
+        }
+
+        let data = packets
+            .iter()
+            .flat_map(|p| p[DDP_HEADER_LEN..].to_vec())
+            .collect::<Vec<_>>();
+        let expected = frame
+            .iter()
+            .flat_map(|c| [c.r, c.g, c.b])
+            .collect::<Vec<_>>();
+        assert_eq!(data, expected);
+    }
+
+    #[tokio::test]
+    async fn single_full_packet_is_pushed() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut ddp = DdpStrip::new(receiver.local_addr().unwrap()).await.unwrap();
+        let frame = vec![RGB8::new(1, 2, 3); DDP_MAX_DATA_LEN / 3];
+        ddp.write_frame(&frame).await.unwrap();
+        ddp.write_frame(&frame).await.unwrap();
+
+        let packets = receive(&receiver, 2).await;
+        for (packet, sequence) in packets.iter().zip([1, 2]) {
+            assert_eq!(packet[0], DDP_FLAGS_VER1 | DDP_FLAGS_PUSH);
+            assert_eq!(packet[1], sequence);
+            assert_eq!(packet[4..8], [0; 4]);
+            assert_eq!(packet.len(), DDP_HEADER_LEN + DDP_MAX_DATA_LEN);
+        }
+    }
+
+    #[tokio::test]
+    async fn sequence_wraps_to_one() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut ddp = DdpStrip::new(receiver.local_addr().unwrap()).await.unwrap();
+        for _ in 0..16 {
+            ddp.write_frame(&[RGB8::default()]).await.unwrap();
+        }
+        let sequences = receive(&receiver, 16)
+            .await
+            .iter()
+            .map(|p| p[1])
+            .collect::<Vec<_>>();
+        assert_eq!(sequences[14], 15);
+        assert_eq!(sequences[15], 1);
+    }
+}
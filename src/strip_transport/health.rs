@@ -0,0 +1,177 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use smart_leds::RGB8;
+use tokio::time::{self, Instant};
+
+use super::FrameSink;
+use crate::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Health {
+    #[default]
+    Healthy,
+    /// Recent writes failed, but fewer than the policy's `fail_after` in a row.
+    Degraded,
+    /// Too many writes failed in a row, frames are dropped until the next retry.
+    Failed,
+}
+
+/// How a guarded sink reacts to write errors. Errors of a `critical` sink are
+/// passed on, any other sink is retried with exponential backoff from `retry`
+/// up to `retry_max` once `fail_after` writes in a row have failed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HealthPolicy {
+    pub critical: bool,
+    pub fail_after: u32,
+    pub retry: time::Duration,
+    pub retry_max: time::Duration,
+}
+
+impl HealthPolicy {
+    pub fn critical(mut self) -> Self {
+        self.critical = true;
+        self
+    }
+}
+
+impl Default for HealthPolicy {
+    fn default() -> Self {
+        HealthPolicy {
+            critical: false,
+            fail_after: 3,
+            retry: time::Duration::from_secs(1),
+            retry_max: time::Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HealthStatus {
+    pub health: Health,
+    /// Failed writes since the sink was created.
+    pub errors: u64,
+    pub last_error: Option<String>,
+}
+
+/// Shared view of a guarded sink's [`HealthStatus`], readable while the sink
+/// is busy writing in its task.
+#[derive(Debug, Clone, Default)]
+pub struct HealthMonitor(Arc<Mutex<HealthStatus>>);
+
+impl HealthMonitor {
+    pub fn status(&self) -> HealthStatus {
+        self.0.lock().unwrap().clone()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut HealthStatus)) {
+        f(&mut self.0.lock().unwrap())
+    }
+}
+
+/// A sink whose errors are handled according to a [`HealthPolicy`].
+pub(super) struct Guarded {
+    sink: Box<dyn FrameSink>,
+    policy: HealthPolicy,
+    monitor: HealthMonitor,
+    failures: u32,
+    backoff: time::Duration,
+    retry_at: Option<Instant>,
+}
+
+impl Guarded {
+    pub(super) fn new(sink: Box<dyn FrameSink>, policy: HealthPolicy) -> Self {
+        Guarded {
+            sink,
+            policy,
+            monitor: HealthMonitor::default(),
+            failures: 0,
+            backoff: policy.retry,
+            retry_at: None,
+        }
+    }
+
+    fn failed(&mut self, error: &crate::Error) {
+        self.failures += 1;
+        let health = match self.failures >= self.policy.fail_after {
+            true => Health::Failed,
+            false => Health::Degraded,
+        };
+        self.monitor.update(|status| {
+            status.health = health;
+            status.errors += 1;
+            status.last_error = Some(error.to_string());
+        });
+
+        match health {
+            Health::Failed => {
+                println!(
+                    "WARN: {:?} failed, retrying in {:?}: {}",
+                    self.sink, self.backoff, error
+                );
+                self.retry_at = Some(Instant::now() + self.backoff);
+                self.backoff = (self.backoff * 2).min(self.policy.retry_max);
+            }
+            _ => println!("WARN: {:?} write failed: {}", self.sink, error),
+        }
+    }
+}
+
+impl std::fmt::Debug for Guarded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.sink)
+    }
+}
+
+#[async_trait]
+impl FrameSink for Guarded {
+    fn kind(&self) -> &'static str {
+        self.sink.kind()
+    }
+
+    fn refresh(&self) -> super::Refresh {
+        self.sink.refresh()
+    }
+
+    fn spawned(&self) -> bool {
+        self.sink.spawned()
+    }
+
+    fn monitor(&self) -> Option<HealthMonitor> {
+        Some(self.monitor.clone())
+    }
+
+    async fn write_frame(&mut self, frame: &[RGB8]) -> Result<()> {
+        if matches!(self.retry_at, Some(at) if Instant::now() < at) {
+            return Ok(());
+        }
+        match self.sink.write_frame(frame).await {
+            Ok(()) => {
+                if self.failures > 0 {
+                    println!("{:?} recovered after {} errors", self.sink, self.failures);
+                    self.monitor
+                        .update(|status| status.health = Health::Healthy);
+                }
+                self.failures = 0;
+                self.backoff = self.policy.retry;
+                self.retry_at = None;
+                Ok(())
+            }
+            Err(e) if self.policy.critical => Err(e),
+            Err(e) => {
+                self.failed(&e);
+                Ok(())
+            }
+        }
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        match self.sink.close().await {
+            Err(e) if !self.policy.critical => {
+                println!("WARN: closing {:?} failed: {}", self.sink, e);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+}
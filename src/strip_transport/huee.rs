@@ -2,114 +2,419 @@ use smart_leds::RGB8;
 
 use std::sync::Arc;
 use tokio::net::UdpSocket;
+use tokio::time;
 use webrtc_dtls::cipher_suite::CipherSuiteId;
 use webrtc_dtls::Error;
 use webrtc_dtls::{config::*, conn::DTLSConn};
 use webrtc_util::conn::Conn;
 
+mod areas;
+mod color;
+mod credentials;
+mod discovery;
+mod tls;
+
+pub use areas::{list_areas, HueAreaInfo, HueLightInfo};
+use color::Gamut;
+pub use credentials::HueCredentials;
+pub use discovery::discover_bridge;
+
+const HUE_V1_HEADER_LEN: usize = 16;
+const HUE_V1_LIGHT_LEN: usize = 9;
+const HUE_V2_ID_LEN: usize = 36;
+const HUE_V2_HEADER_LEN: usize = HUE_V1_HEADER_LEN + HUE_V2_ID_LEN;
+const HUE_V2_CHANNEL_LEN: usize = 7;
+const HUE_V2_MAX_CHANNELS: usize = 20;
+// the bridge recommends 25-50 Hz and ends the stream after 10s without data
+const HUE_MAX_FPS: f64 = 50.0;
+const HUE_KEEPALIVE: time::Duration = time::Duration::from_secs(1);
+
+/// The streaming target on the bridge, either a v1 entertainment group or a
+/// v2 (CLIP v2) entertainment configuration.
+#[derive(Debug, Clone)]
+pub enum HueArea {
+    Group(u16),
+    Entertainment(String),
+}
+
+impl std::fmt::Display for HueArea {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HueArea::Group(id) => write!(f, "{}", id),
+            HueArea::Entertainment(id) => write!(f, "v2:{}", id),
+        }
+    }
+}
+
+impl HueArea {
+    /// Checks a v2 id is a 36 character UUID, as the stream header expects.
+    fn validate(&self) -> crate::Result<()> {
+        let is_uuid = |id: &str| {
+            id.len() == HUE_V2_ID_LEN
+                && id.bytes().enumerate().all(|(i, b)| match i {
+                    8 | 13 | 18 | 23 => b == b'-',
+                    _ => b.is_ascii_hexdigit(),
+                })
+        };
+        match self {
+            HueArea::Entertainment(id) if !is_uuid(id) => Err(crate::Error::ConfigError(format!(
+                "invalid entertainment configuration {:?}, expected a UUID",
+                id
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    fn url(&self, hub_ip: &str, username: &str) -> String {
+        match self {
+            HueArea::Group(id) => format!("https://{}/api/{}/groups/{}", hub_ip, username, id),
+            HueArea::Entertainment(id) => format!(
+                "https://{}/clip/v2/resource/entertainment_configuration/{}",
+                hub_ip, id
+            ),
+        }
+    }
+
+    fn stream_body(&self, active: bool) -> &'static str {
+        match (self, active) {
+            (HueArea::Group(_), true) => "{\"stream\":{\"active\":true}}",
+            (HueArea::Group(_), false) => "{\"stream\":{\"active\":false}}",
+            (HueArea::Entertainment(_), true) => "{\"action\":\"start\"}",
+            (HueArea::Entertainment(_), false) => "{\"action\":\"stop\"}",
+        }
+    }
+
+    fn request(
+        &self,
+        client: &reqwest::Client,
+        method: reqwest::Method,
+        hub_ip: &str,
+        username: &str,
+    ) -> reqwest::RequestBuilder {
+        let request = client.request(method, self.url(hub_ip, username));
+        match self {
+            HueArea::Group(_) => request,
+            HueArea::Entertainment(_) => request.header("hue-application-key", username),
+        }
+    }
+}
+
+/// Colour encoding of the stream, xy+brightness is clamped to each light's
+/// gamut here rather than leaving the bridge to approximate RGB values.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HueColorSpace {
+    Rgb,
+    XyBrightness,
+}
+
+impl std::str::FromStr for HueColorSpace {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s {
+            "rgb" => Ok(HueColorSpace::Rgb),
+            "xy" => Ok(HueColorSpace::XyBrightness),
+            _ => Err(crate::Error::ConfigError(format!(
+                "unknown hue color space {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// A single addressable output, a light in a v1 group or a channel (which may
+/// be one segment of a gradient light) in a v2 entertainment configuration.
+#[derive(Debug, Clone)]
+pub(crate) struct HueChannel {
+    pub(crate) id: u16,
+    pub(crate) position: Option<[f64; 3]>,
+    gamut: Option<Gamut>,
+}
+
+/// Connection state of the entertainment stream. While reconnecting every
+/// write tries again and fails until it succeeds, how often is left to the
+/// [`HealthPolicy`](super::HealthPolicy) guarding the sink.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum HueState {
+    Connected,
+    Reconnecting { attempts: u32 },
+    Closed,
+}
+
+impl std::fmt::Display for HueState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HueState::Connected => f.write_str("connected"),
+            HueState::Reconnecting { attempts, .. } => {
+                write!(f, "reconnecting, {} attempts", attempts)
+            }
+            HueState::Closed => f.write_str("closed"),
+        }
+    }
+}
+
 #[allow(dead_code)]
 pub struct Hue {
     pub(crate) desc: String,
     hub_ip: String,
     username: String,
-    clientkey: String,
-    group: u16,
-    lights: Vec<u16>,
+    psk: Vec<u8>,
+    client: reqwest::Client,
+    area: HueArea,
+    color_space: HueColorSpace,
+    channels: Vec<HueChannel>,
+    positional: bool,
     dtls_conn: Option<Arc<dyn Conn + Send + Sync>>,
-    buf: [u8; 106],
+    pub(crate) state: HueState,
+    sequence: u8,
+    buf: Vec<u8>,
+}
+
+impl std::fmt::Debug for Hue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "hue:{} ({})", self.desc, self.state)
+    }
 }
 
 impl Drop for Hue {
     fn drop(&mut self) {
-        let group_url = format!(
-            "https://{}/api/{}/groups/{}",
-            self.hub_ip, self.username, self.group
-        );
-        let desc = self.desc.clone();
-        tokio::spawn(async move {
-            println!("disconnecting from {}", desc);
-            reqwest::Client::builder()
-                .danger_accept_invalid_certs(true)
-                .build()
-                .unwrap()
-                .put(&group_url)
-                .body("{\"stream\":{\"active\":false}}")
-                .send()
-                .await
-                .ok()
-        });
+        if !matches!(self.state, HueState::Closed) {
+            println!(
+                "WARN: {} dropped without closing, stream left active",
+                self.desc
+            );
+        }
     }
 }
 
 impl Hue {
-    pub(crate) async fn new(
+    pub async fn new(
         hub_ip: &str,
-        username: &str,
-        clientkey: &str,
-        group: u16,
+        credentials: &HueCredentials,
+        area: HueArea,
+        color_space: HueColorSpace,
     ) -> super::Result<Self> {
-        let group_url = format!("https://{}/api/{}/groups/{}", hub_ip, username, group);
-        let client = reqwest::Client::builder()
-            .danger_accept_invalid_certs(true)
-            .build()
-            .unwrap();
-
-        client
-            .put(&group_url)
-            .body("{\"stream\":{\"active\":true}}")
-            .send()
-            .await?
-            .text()
-            .await?;
+        area.validate()?;
+        let username = credentials.username.as_str();
+        let client = credentials.client()?;
 
-        let grp = client
-            .get(&group_url)
-            .send()
-            .await?
-            .json::<serde_json::Value>()
-            .await?;
+        Self::set_streaming(&client, &area, hub_ip, username, true).await?;
 
-        let lights: Vec<u16> = Self::get_lights(grp).unwrap();
+        let grp = get_json(area.request(&client, reqwest::Method::GET, hub_ip, username)).await?;
+
+        let config = match area {
+            HueArea::Group(_) => &grp,
+            HueArea::Entertainment(_) => &grp["data"][0],
+        };
+        let mut channels: Vec<HueChannel> = match area {
+            HueArea::Group(_) => Self::get_lights(config),
+            HueArea::Entertainment(_) => Self::get_channels(config),
+        }
+        .ok_or_else(|| {
+            crate::Error::HueError(format!("no lights found for {} on {}", area, hub_ip))
+        })?;
+
+        if color_space == HueColorSpace::XyBrightness {
+            let lights =
+                Self::get_channel_lights(&client, &area, hub_ip, username, config, &channels)
+                    .await?;
+            for (channel, light) in channels.iter_mut().zip(lights.iter()) {
+                let gamut = match area {
+                    HueArea::Group(_) => &light["capabilities"]["control"]["colorgamuttype"],
+                    HueArea::Entertainment(_) => &light["color"]["gamut_type"],
+                };
+                channel.gamut = Gamut::parse(gamut.as_str());
+            }
+        }
 
         let mut hue = Hue {
-            desc: format!("{}/{}{:?}", hub_ip, group, lights),
+            desc: format!(
+                "{}/{}{:?}",
+                hub_ip,
+                area,
+                channels.iter().map(|c| c.id).collect::<Vec<_>>()
+            ),
             hub_ip: String::from(hub_ip),
             username: String::from(username),
-            clientkey: String::from(clientkey),
-            group,
-            lights,
+            psk: credentials.psk()?,
+            client,
+            area,
+            color_space,
+            channels,
+            positional: false,
             dtls_conn: None,
-            buf: [0; 106],
+            state: HueState::Connected,
+            sequence: 0,
+            buf: Vec::new(),
         };
 
-        hue.buf[..9].copy_from_slice("HueStream".as_bytes());
-        hue.buf[9..16].copy_from_slice(&[1 as u8, 0, 0, 0, 0, 0, 0]);
+        hue.write_header();
         hue.connect().await?;
 
         Ok(hue)
     }
 
-    fn get_lights(group: serde_json::Value) -> Option<Vec<u16>> {
+    async fn set_streaming(
+        client: &reqwest::Client,
+        area: &HueArea,
+        hub_ip: &str,
+        username: &str,
+        active: bool,
+    ) -> super::Result<()> {
+        area.request(client, reqwest::Method::PUT, hub_ip, username)
+            .body(area.stream_body(active))
+            .send()
+            .await?
+            .text()
+            .await?;
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> super::Result<()> {
+        Self::set_streaming(&self.client, &self.area, &self.hub_ip, &self.username, true).await?;
+        self.connect().await?;
+        Ok(())
+    }
+
+    fn get_lights(group: &serde_json::Value) -> Option<Vec<HueChannel>> {
         group["lights"]
             .as_array()?
             .iter()
-            .filter_map(|light| light.as_str().map(|x| x.parse::<u16>().ok()))
+            .map(|light| {
+                let light = light.as_str()?;
+                let position = location(&group["locations"][light]);
+                Some(HueChannel {
+                    id: light.parse::<u16>().ok()?,
+                    position,
+                    gamut: None,
+                })
+            })
+            .collect()
+    }
+
+    fn get_channels(config: &serde_json::Value) -> Option<Vec<HueChannel>> {
+        config["channels"]
+            .as_array()?
+            .iter()
+            .take(HUE_V2_MAX_CHANNELS)
+            .map(|channel| {
+                let position = &channel["position"];
+                Some(HueChannel {
+                    id: channel["channel_id"].as_u64()? as u16,
+                    position: Some([
+                        position["x"].as_f64()?,
+                        position["y"].as_f64()?,
+                        position["z"].as_f64()?,
+                    ]),
+                    gamut: None,
+                })
+            })
             .collect()
     }
 
+    /// Looks up the light resource behind each channel, the v1 light object
+    /// for groups or the v2 light rendering a channel's entertainment service.
+    async fn get_channel_lights(
+        client: &reqwest::Client,
+        area: &HueArea,
+        hub_ip: &str,
+        username: &str,
+        config: &serde_json::Value,
+        channels: &[HueChannel],
+    ) -> super::Result<Vec<serde_json::Value>> {
+        match area {
+            HueArea::Group(_) => {
+                let lights =
+                    get_json(client.get(format!("https://{}/api/{}/lights", hub_ip, username)))
+                        .await?;
+
+                Ok(channels
+                    .iter()
+                    .map(|channel| lights[channel.id.to_string()].clone())
+                    .collect())
+            }
+            HueArea::Entertainment(_) => {
+                let resource = |kind: &str| {
+                    client
+                        .get(format!("https://{}/clip/v2/resource/{}", hub_ip, kind))
+                        .header("hue-application-key", username)
+                };
+                let services = get_json(resource("entertainment")).await?;
+                let lights = get_json(resource("light")).await?;
+
+                let find = |resources: &serde_json::Value, id: Option<&str>| {
+                    resources["data"]
+                        .as_array()
+                        .and_then(|data| data.iter().find(|r| r["id"].as_str() == id))
+                        .cloned()
+                        .unwrap_or_default()
+                };
+
+                Ok(channels
+                    .iter()
+                    .map(|channel| {
+                        let member = config["channels"]
+                            .as_array()
+                            .and_then(|all| {
+                                all.iter()
+                                    .find(|c| c["channel_id"].as_u64() == Some(channel.id as u64))
+                            })
+                            .map(|c| c["members"][0]["service"]["rid"].clone())
+                            .unwrap_or_default();
+                        let service = find(&services, member.as_str());
+                        find(&lights, service["renderer_reference"]["rid"].as_str())
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    fn write_header(&mut self) {
+        self.buf.clear();
+        self.buf.extend_from_slice("HueStream".as_bytes());
+        let color_space = match self.color_space {
+            HueColorSpace::Rgb => 0,
+            HueColorSpace::XyBrightness => 1,
+        };
+        match &self.area {
+            HueArea::Group(_) => {
+                self.buf.extend_from_slice(&[1, 0, 0, 0, 0, color_space, 0]);
+            }
+            HueArea::Entertainment(id) => {
+                self.buf.extend_from_slice(&[2, 0, 0, 0, 0, color_space, 0]);
+                self.buf.extend_from_slice(id.as_bytes());
+            }
+        }
+    }
+
+    /// Assigns frame pixels to lights by their left to right (x) position in
+    /// the entertainment area rather than by their order in the group.
+    pub fn set_positional(&mut self, positional: bool) {
+        self.positional = positional;
+    }
+
+    fn frame_index(&self, idx: usize, channel: &HueChannel, len: usize) -> usize {
+        match (self.positional, channel.position) {
+            (true, Some([x, _, _])) => (((x.clamp(-1.0, 1.0) + 1.0) / 2.0 * len as f64) as usize)
+                .min(len.saturating_sub(1)),
+            _ => idx,
+        }
+    }
+
+    fn encode(&self, l: RGB8, channel: &HueChannel) -> [u16; 3] {
+        match self.color_space {
+            HueColorSpace::Rgb => [l.r, l.g, l.b].map(|c| c as u16 * 257),
+            HueColorSpace::XyBrightness => color::xy_brightness(l, channel.gamut),
+        }
+    }
+
     async fn connect(&mut self) -> Result<(), Error> {
         let conn = Arc::new(UdpSocket::bind("0.0.0.0:0").await?);
         conn.connect(format!("{}:2100", self.hub_ip)).await?;
         println!("connecting {}..", self.hub_ip);
 
-        fn decode_hex(s: &str) -> Result<Vec<u8>, std::num::ParseIntError> {
-            (0..s.len())
-                .step_by(2)
-                .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
-                .collect()
-        }
-
-        let key = Arc::new(decode_hex(&self.clientkey).unwrap());
+        let key = Arc::new(self.psk.clone());
 
         let config = Config {
             psk: Some(Arc::new(move |_hint: &[u8]| Ok(key.as_ref().clone()))),
@@ -127,6 +432,17 @@ impl Hue {
     }
 }
 
+fn location(location: &serde_json::Value) -> Option<[f64; 3]> {
+    match location.as_array()?.as_slice() {
+        [x, y, z, ..] => Some([x.as_f64()?, y.as_f64()?, z.as_f64()?]),
+        _ => None,
+    }
+}
+
+async fn get_json(request: reqwest::RequestBuilder) -> super::Result<serde_json::Value> {
+    Ok(request.send().await?.json::<serde_json::Value>().await?)
+}
+
 #[async_trait::async_trait]
 impl super::AsyncSmartLedsWrite for Hue {
     type Error = crate::Error;
@@ -137,30 +453,115 @@ impl super::AsyncSmartLedsWrite for Hue {
         T: Iterator<Item = I> + Send,
         I: Into<Self::Color>,
     {
-        let mut len = 16;
-        self.buf[16..]
-            .iter_mut()
-            .zip(iterator.zip(self.lights.iter()).flat_map(|(item, id)| {
-                len += 9;
-                let l = item.into();
-                let lid = id.to_be_bytes();
-                [0, lid[0], lid[1], l.r, l.r, l.g, l.g, l.b, l.g]
-            }))
-            .for_each(|(dst, itm)| *dst = itm);
-        match self.dtls_conn.as_ref() {
-            Some(c) => {
-                let con = Arc::clone(c);
-                con.as_ref()
-                    .send(&self.buf[..len])
-                    .await
-                    .unwrap_or_else(|e| {
-                        println!("WARN: disconnected from hue: {:?}", e);
-                        self.dtls_conn = None;
-                        0
-                    });
+        if let HueState::Closed = self.state {
+            return Ok(());
+        }
+        if let HueState::Reconnecting { attempts } = self.state {
+            if let Err(e) = self.reconnect().await {
+                self.state = HueState::Reconnecting {
+                    attempts: attempts + 1,
+                };
+                return Err(crate::Error::HueError(format!(
+                    "reconnecting to hue {} failed (attempt {}): {}",
+                    self.desc,
+                    attempts + 1,
+                    e
+                )));
             }
-            None => (),
-        };
+            println!(
+                "reconnected to hue {} after {} attempts",
+                self.desc,
+                attempts + 1
+            );
+            self.state = HueState::Connected;
+        }
+
+        let frame: Vec<RGB8> = iterator.map(|item| item.into()).collect();
+        let lights: Vec<(u16, [u16; 3])> = self
+            .channels
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, channel)| {
+                let l = frame.get(self.frame_index(idx, channel, frame.len()))?;
+                Some((channel.id, self.encode(*l, channel)))
+            })
+            .collect();
+
+        match self.area {
+            HueArea::Group(_) => {
+                self.buf.truncate(HUE_V1_HEADER_LEN);
+                self.buf.reserve(lights.len() * HUE_V1_LIGHT_LEN);
+                for (id, color) in lights {
+                    self.buf.push(0);
+                    self.buf.extend_from_slice(&id.to_be_bytes());
+                    color
+                        .iter()
+                        .for_each(|c| self.buf.extend_from_slice(&c.to_be_bytes()));
+                }
+            }
+            HueArea::Entertainment(_) => {
+                self.sequence = self.sequence.wrapping_add(1);
+                self.buf[11] = self.sequence;
+                self.buf.truncate(HUE_V2_HEADER_LEN);
+                self.buf.reserve(lights.len() * HUE_V2_CHANNEL_LEN);
+                for (id, color) in lights {
+                    self.buf.push(id as u8);
+                    color
+                        .iter()
+                        .for_each(|c| self.buf.extend_from_slice(&c.to_be_bytes()));
+                }
+            }
+        }
+
+        if let Some(c) = self.dtls_conn.as_ref() {
+            if let Err(e) = Arc::clone(c).send(&self.buf).await {
+                self.dtls_conn = None;
+                self.state = HueState::Reconnecting { attempts: 0 };
+                return Err(crate::Error::HueError(format!(
+                    "disconnected from hue {}: {:?}",
+                    self.desc, e
+                )));
+            }
+        }
         Ok(())
     }
+
+    async fn close(&mut self) -> crate::Result<()> {
+        println!("disconnecting from {}", self.desc);
+        self.state = HueState::Closed;
+        if let Some(c) = self.dtls_conn.take() {
+            c.close().await.ok();
+        }
+        Self::set_streaming(
+            &self.client,
+            &self.area,
+            &self.hub_ip,
+            &self.username,
+            false,
+        )
+        .await
+    }
+}
+
+#[async_trait::async_trait]
+impl super::FrameSink for Hue {
+    fn kind(&self) -> &'static str {
+        "hue"
+    }
+
+    fn refresh(&self) -> super::Refresh {
+        super::Refresh {
+            max_fps: Some(HUE_MAX_FPS),
+            keepalive: Some(HUE_KEEPALIVE),
+            ..Default::default()
+        }
+    }
+
+    async fn write_frame(&mut self, frame: &[RGB8]) -> crate::Result<()> {
+        super::AsyncSmartLedsWrite::write(self, frame.iter().copied()).await
+    }
+
+    async fn close(&mut self) -> crate::Result<()> {
+        super::AsyncSmartLedsWrite::close(self).await
+    }
 }
@@ -0,0 +1,114 @@
+use super::{get_json, Hue, HueArea, HueChannel, HueCredentials};
+use crate::Result;
+
+/// An entertainment group (v1) or configuration (v2) as reported by the bridge.
+#[derive(Debug, Clone)]
+pub struct HueAreaInfo {
+    pub area: HueArea,
+    pub name: String,
+    pub lights: Vec<HueLightInfo>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HueLightInfo {
+    pub id: u16,
+    pub name: String,
+    pub kind: String,
+    pub position: Option<[f64; 3]>,
+}
+
+impl std::fmt::Display for HueAreaInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} \"{}\"", self.area, self.name)?;
+        for light in &self.lights {
+            write!(f, "  {:>3}: \"{}\" ({})", light.id, light.name, light.kind)?;
+            match light.position {
+                Some([x, y, z]) => writeln!(f, " at [{:.2}, {:.2}, {:.2}]", x, y, z)?,
+                None => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lists every entertainment area on the bridge, both v1 entertainment groups
+/// and v2 entertainment configurations.
+pub async fn list_areas(hub_ip: &str, credentials: &HueCredentials) -> Result<Vec<HueAreaInfo>> {
+    let username = credentials.username.as_str();
+    let client = credentials.client()?;
+    let mut areas = Vec::new();
+
+    let groups =
+        get_json(client.get(format!("https://{}/api/{}/groups", hub_ip, username))).await?;
+    for (id, group) in groups.as_object().into_iter().flatten() {
+        if group["type"].as_str() != Some("Entertainment") {
+            continue;
+        }
+        let area = match id.parse::<u16>() {
+            Ok(id) => HueArea::Group(id),
+            Err(_) => continue,
+        };
+        let channels = Hue::get_lights(group).unwrap_or_default();
+        let lights =
+            Hue::get_channel_lights(&client, &area, hub_ip, username, group, &channels).await?;
+        areas.push(HueAreaInfo {
+            name: group["name"].as_str().unwrap_or_default().to_string(),
+            lights: describe(&channels, &lights, |light| (&light["name"], &light["type"])),
+            area,
+        });
+    }
+
+    let configs = get_json(
+        client
+            .get(format!(
+                "https://{}/clip/v2/resource/entertainment_configuration",
+                hub_ip
+            ))
+            .header("hue-application-key", username),
+    )
+    .await?;
+    for config in configs["data"].as_array().into_iter().flatten() {
+        let area = match config["id"].as_str() {
+            Some(id) => HueArea::Entertainment(String::from(id)),
+            None => continue,
+        };
+        let channels = Hue::get_channels(config).unwrap_or_default();
+        let lights =
+            Hue::get_channel_lights(&client, &area, hub_ip, username, config, &channels).await?;
+        areas.push(HueAreaInfo {
+            name: config["metadata"]["name"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            lights: describe(&channels, &lights, |light| {
+                (&light["metadata"]["name"], &light["metadata"]["archetype"])
+            }),
+            area,
+        });
+    }
+
+    Ok(areas)
+}
+
+fn describe<F>(
+    channels: &[HueChannel],
+    lights: &[serde_json::Value],
+    fields: F,
+) -> Vec<HueLightInfo>
+where
+    F: Fn(&serde_json::Value) -> (&serde_json::Value, &serde_json::Value),
+{
+    channels
+        .iter()
+        .zip(lights.iter())
+        .map(|(channel, light)| {
+            let (name, kind) = fields(light);
+            HueLightInfo {
+                id: channel.id,
+                name: name.as_str().unwrap_or("?").to_string(),
+                kind: kind.as_str().unwrap_or("?").to_string(),
+                position: channel.position,
+            }
+        })
+        .collect()
+}
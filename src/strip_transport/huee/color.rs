@@ -0,0 +1,156 @@
+use palette::{IntoColor, Srgb, Yxy};
+use smart_leds::RGB8;
+
+type Point = (f32, f32);
+
+/// Colour gamut triangles (red, green, blue) as reported by the bridge.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Gamut {
+    A,
+    B,
+    C,
+}
+
+impl Gamut {
+    pub(crate) fn parse(gamut: Option<&str>) -> Option<Self> {
+        match gamut? {
+            "A" => Some(Gamut::A),
+            "B" => Some(Gamut::B),
+            "C" => Some(Gamut::C),
+            _ => None,
+        }
+    }
+
+    fn triangle(&self) -> [Point; 3] {
+        match self {
+            Gamut::A => [(0.704, 0.296), (0.2151, 0.7106), (0.138, 0.08)],
+            Gamut::B => [(0.675, 0.322), (0.409, 0.518), (0.167, 0.04)],
+            Gamut::C => [(0.6915, 0.3083), (0.17, 0.7), (0.1532, 0.0475)],
+        }
+    }
+
+    fn clamp(&self, p: Point) -> Point {
+        let [r, g, b] = self.triangle();
+        if contains([r, g, b], p) {
+            return p;
+        }
+        [closest(r, g, p), closest(g, b, p), closest(b, r, p)]
+            .into_iter()
+            .min_by(|a, b| distance(*a, p).total_cmp(&distance(*b, p)))
+            .unwrap_or(p)
+    }
+}
+
+fn cross(o: Point, a: Point, b: Point) -> f32 {
+    (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+}
+
+fn contains([r, g, b]: [Point; 3], p: Point) -> bool {
+    let (d1, d2, d3) = (cross(r, g, p), cross(g, b, p), cross(b, r, p));
+    let has_neg = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+    let has_pos = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+    !(has_neg && has_pos)
+}
+
+fn closest(a: Point, b: Point, p: Point) -> Point {
+    let ab = (b.0 - a.0, b.1 - a.1);
+    let t = ((p.0 - a.0) * ab.0 + (p.1 - a.1) * ab.1) / (ab.0 * ab.0 + ab.1 * ab.1);
+    let t = t.clamp(0.0, 1.0);
+    (a.0 + ab.0 * t, a.1 + ab.1 * t)
+}
+
+fn distance(a: Point, b: Point) -> f32 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
+
+/// Converts to CIE xy chromaticity plus brightness, each scaled to 16 bits,
+/// clamping the chromaticity into the light's gamut when it is known.
+pub(crate) fn xy_brightness(l: RGB8, gamut: Option<Gamut>) -> [u16; 3] {
+    let yxy: Yxy = if l == RGB8::default() {
+        // black has no chromaticity, use the white point at zero brightness
+        Yxy::default()
+    } else {
+        Srgb::new(l.r, l.g, l.b).into_format::<f32>().into_color()
+    };
+    let (x, y) = match gamut {
+        Some(gamut) => gamut.clamp((yxy.x, yxy.y)),
+        None => (yxy.x, yxy.y),
+    };
+    [x, y, yxy.luma].map(|c| (c.clamp(0.0, 1.0) * u16::MAX as f32) as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-3;
+
+    fn assert_near(a: Point, b: Point) {
+        assert!(distance(a, b) < EPSILON, "{:?} != {:?}", a, b);
+    }
+
+    fn unscale(c: u16) -> f32 {
+        c as f32 / u16::MAX as f32
+    }
+
+    #[test]
+    fn parses_gamuts() {
+        assert_eq!(Gamut::parse(Some("A")), Some(Gamut::A));
+        assert_eq!(Gamut::parse(Some("C")), Some(Gamut::C));
+        assert_eq!(Gamut::parse(Some("other")), None);
+        assert_eq!(Gamut::parse(None), None);
+    }
+
+    #[test]
+    fn keeps_points_inside_gamut() {
+        for gamut in [Gamut::A, Gamut::B, Gamut::C] {
+            for corner in gamut.triangle() {
+                assert_near(gamut.clamp(corner), corner);
+            }
+            assert_near(gamut.clamp((0.4, 0.4)), (0.4, 0.4));
+        }
+    }
+
+    #[test]
+    fn clamps_points_outside_gamut() {
+        // beyond the red corner, past both edges meeting there
+        let [red, green, blue] = Gamut::A.triangle();
+        assert_near(Gamut::A.clamp((red.0 + 0.1, red.1)), red);
+
+        // below the middle of the blue-red edge
+        let mid = ((blue.0 + red.0) / 2.0, (blue.1 + red.1) / 2.0);
+        let normal = (red.1 - blue.1, blue.0 - red.0);
+        let outside = (mid.0 + normal.0 * 0.1, mid.1 + normal.1 * 0.1);
+        assert!(!contains([red, green, blue], outside));
+        assert_near(Gamut::A.clamp(outside), mid);
+
+        // sRGB green lies outside gamut B, it lands on the red-green edge
+        let [red, green, _] = Gamut::B.triangle();
+        let clamped = Gamut::B.clamp((0.3, 0.6));
+        assert_near(clamped, closest(red, green, (0.3, 0.6)));
+        assert!(distance(clamped, (0.3, 0.6)) > 0.01);
+    }
+
+    #[test]
+    fn converts_rgb_to_xy_brightness() {
+        let [x, y, bri] = xy_brightness(RGB8::new(255, 255, 255), None);
+        assert_near((unscale(x), unscale(y)), (0.3127, 0.3290));
+        assert_eq!(bri, u16::MAX);
+
+        let [x, y, _] = xy_brightness(RGB8::new(255, 0, 0), None);
+        assert_near((unscale(x), unscale(y)), (0.64, 0.33));
+
+        let [_, _, bri] = xy_brightness(RGB8::new(128, 128, 128), None);
+        assert!((unscale(bri) - 0.2158).abs() < EPSILON);
+
+        assert_eq!(xy_brightness(RGB8::default(), Some(Gamut::C))[2], 0);
+    }
+
+    #[test]
+    fn clamps_converted_colours_into_gamut() {
+        let [x, y, _] = xy_brightness(RGB8::new(0, 255, 0), Some(Gamut::B));
+        let [red, green, _] = Gamut::B.triangle();
+        let xy = (unscale(x), unscale(y));
+        assert_near(xy, closest(red, green, (0.3, 0.6)));
+    }
+}
@@ -0,0 +1,288 @@
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::OpenOptionsExt;
+
+use tokio::time;
+
+use super::tls;
+use crate::{Error, Result};
+
+const HUE_PAIR_ATTEMPTS: u32 = 60;
+const HUE_LINK_BUTTON_NOT_PRESSED: u64 = 101;
+
+/// Application key (`username`) and DTLS pre-shared `clientkey` issued by a
+/// bridge when pairing, along with the id of the bridge that issued them and
+/// the fingerprint of its certificate at the time.
+#[derive(Debug, Clone)]
+pub struct HueCredentials {
+    pub username: String,
+    pub clientkey: String,
+    pub bridge_id: Option<String>,
+    pub certificate: Option<String>,
+}
+
+impl HueCredentials {
+    pub fn load(path: &str) -> Result<Self> {
+        let creds: serde_json::Value = serde_json::from_reader(File::open(path)?)?;
+        let field = |name: &str| {
+            creds[name]
+                .as_str()
+                .map(String::from)
+                .ok_or_else(|| Error::HueError(format!("missing {} in {}", name, path)))
+        };
+        let credentials = HueCredentials {
+            username: field("username")?,
+            clientkey: field("clientkey")?,
+            bridge_id: field("bridgeid").ok(),
+            certificate: field("certificate").ok(),
+        };
+        credentials.psk()?;
+        Ok(credentials)
+    }
+
+    /// The `clientkey` decoded from hex, the pre-shared key of the stream.
+    pub(crate) fn psk(&self) -> Result<Vec<u8>> {
+        let invalid = || Error::HueError(String::from("clientkey is not a hex string"));
+        let key = self.clientkey.as_bytes();
+        let pairs = key.chunks_exact(2);
+        if key.is_empty() || !pairs.remainder().is_empty() || !key.iter().all(u8::is_ascii_hexdigit)
+        {
+            return Err(invalid());
+        }
+        pairs
+            .map(|pair| {
+                let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+                u8::from_str_radix(pair, 16).map_err(|_| invalid())
+            })
+            .collect()
+    }
+
+    /// A client for REST calls to the bridge these credentials belong to.
+    pub fn client(&self) -> Result<reqwest::Client> {
+        let (client, _) = tls::client(
+            self.bridge_id.as_deref(),
+            self.certificate.as_deref(),
+            false,
+        )?;
+        Ok(client)
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?;
+        serde_json::to_writer_pretty(
+            file,
+            &serde_json::json!({
+                "username": self.username,
+                "clientkey": self.clientkey,
+                "bridgeid": self.bridge_id,
+                "certificate": self.certificate,
+            }),
+        )?;
+        Ok(())
+    }
+
+    /// Registers a new application with the bridge at `bridge_url` (e.g.
+    /// `https://192.168.1.2`), retrying until the link button is pressed. The
+    /// bridge's certificate is pinned at this point, for bridges whose
+    /// certificate isn't signed by the Hue CA.
+    pub async fn pair(bridge_url: &str, devicetype: &str) -> Result<Self> {
+        let (client, certificate) = tls::client(None, None, true)?;
+        let config = client
+            .get(format!("{}/api/config", bridge_url))
+            .send()
+            .await?
+            .json::<serde_json::Value>()
+            .await?;
+        let bridge_id = config["bridgeid"].as_str().map(String::from);
+
+        let body = serde_json::json!({
+            "devicetype": devicetype,
+            "generateclientkey": true,
+        });
+
+        println!("Press the link button on the bridge at {}..", bridge_url);
+        for _ in 0..HUE_PAIR_ATTEMPTS {
+            let response = client
+                .post(format!("{}/api", bridge_url))
+                .json(&body)
+                .send()
+                .await?
+                .json::<serde_json::Value>()
+                .await?;
+
+            let success = &response[0]["success"];
+            if let (Some(username), Some(clientkey)) =
+                (success["username"].as_str(), success["clientkey"].as_str())
+            {
+                return Ok(HueCredentials {
+                    username: String::from(username),
+                    clientkey: String::from(clientkey),
+                    bridge_id,
+                    certificate: certificate.lock().unwrap().clone(),
+                });
+            }
+
+            match response[0]["error"]["type"].as_u64() {
+                Some(HUE_LINK_BUTTON_NOT_PRESSED) => {
+                    time::sleep(time::Duration::from_secs(1)).await
+                }
+                _ => return Err(Error::HueError(format!("pairing failed: {}", response))),
+            }
+        }
+        Err(Error::HueError(String::from(
+            "timed out waiting for the link button",
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    use sha2::{Digest, Sha256};
+
+    use super::HueCredentials;
+
+    const BRIDGE_CERT: &[u8] = include_bytes!("testdata/bridge.pem");
+    const BRIDGE_KEY: &[u8] = include_bytes!("testdata/bridge.key");
+
+    /// Answers like a bridge whose link button is pressed after the first
+    /// pairing attempt.
+    fn serve(stream: TcpStream, config: Arc<rustls::ServerConfig>, attempts: Arc<AtomicU32>) {
+        let tls = rustls::ServerConnection::new(config).unwrap();
+        let mut reader = BufReader::new(rustls::StreamOwned::new(tls, stream));
+        loop {
+            let mut request = String::new();
+            match reader.read_line(&mut request) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => (),
+            }
+            let mut length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header == "\r\n" {
+                    break;
+                }
+                if let Some(value) = header.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            let response = match request.split_whitespace().nth(1) {
+                Some("/api/config") => serde_json::json!({"bridgeid": "001788FFFE000001"}),
+                Some("/api") if attempts.fetch_add(1, Ordering::SeqCst) == 0 => {
+                    serde_json::json!([{"error": {"type": 101, "description": "link button not pressed"}}])
+                }
+                Some("/api") => {
+                    serde_json::json!([{"success": {"username": "user", "clientkey": "0123ABCD"}}])
+                }
+                _ => serde_json::json!([]),
+            }
+            .to_string();
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                response.len(),
+                response
+            )
+            .unwrap();
+        }
+    }
+
+    fn credentials(clientkey: &str) -> HueCredentials {
+        HueCredentials {
+            username: String::from("user"),
+            clientkey: String::from(clientkey),
+            bridge_id: None,
+            certificate: None,
+        }
+    }
+
+    #[test]
+    fn decodes_clientkey() {
+        assert_eq!(
+            credentials("0123abCD").psk().unwrap(),
+            [0x01, 0x23, 0xab, 0xcd]
+        );
+        for invalid in ["", "012", "01zz", "+1", "0\u{e9}"] {
+            assert!(credentials(invalid).psk().is_err(), "{:?}", invalid);
+        }
+    }
+
+    /// Starts a fake bridge with a self-signed certificate, returning its
+    /// address, pairing attempt counter and certificate fingerprint.
+    fn bridge() -> (SocketAddr, Arc<AtomicU32>, String) {
+        let certs = rustls_pemfile::certs(&mut &BRIDGE_CERT[..]).unwrap();
+        let key = rustls_pemfile::pkcs8_private_keys(&mut &BRIDGE_KEY[..])
+            .unwrap()
+            .remove(0);
+        let fingerprint = Sha256::digest(&certs[0])
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        let config = Arc::new(
+            rustls::ServerConfig::builder()
+                .with_safe_defaults()
+                .with_no_client_auth()
+                .with_single_cert(
+                    certs.into_iter().map(rustls::Certificate).collect(),
+                    rustls::PrivateKey(key),
+                )
+                .unwrap(),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let attempts = Arc::new(AtomicU32::new(0));
+        let served = Arc::clone(&attempts);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let (config, attempts) = (Arc::clone(&config), Arc::clone(&served));
+                std::thread::spawn(move || serve(stream.unwrap(), config, attempts));
+            }
+        });
+        (addr, attempts, fingerprint)
+    }
+
+    #[tokio::test]
+    async fn pair_retries_until_link_button_pressed() {
+        let (addr, attempts, fingerprint) = bridge();
+
+        let credentials = HueCredentials::pair(&format!("https://{}", addr), "rwled#test")
+            .await
+            .unwrap();
+
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert_eq!(credentials.username, "user");
+        assert_eq!(credentials.clientkey, "0123ABCD");
+        assert_eq!(credentials.bridge_id.as_deref(), Some("001788FFFE000001"));
+        assert_eq!(credentials.certificate, Some(fingerprint));
+    }
+
+    #[tokio::test]
+    async fn client_only_trusts_pinned_certificate() {
+        let (addr, _, fingerprint) = bridge();
+        let url = format!("https://{}/api/config", addr);
+
+        let mut credentials = credentials("0123ABCD");
+        for pinned in [None, Some("00".repeat(32))] {
+            credentials.certificate = pinned;
+            let client = credentials.client().unwrap();
+            assert!(client.get(&url).send().await.is_err());
+        }
+
+        credentials.certificate = Some(fingerprint);
+        let client = credentials.client().unwrap();
+        assert!(client.get(&url).send().await.is_ok());
+    }
+}
@@ -0,0 +1,204 @@
+use std::net::{IpAddr, SocketAddr};
+
+use tokio::net::UdpSocket;
+use tokio::time;
+
+use crate::Result;
+
+const MDNS_ADDR: &str = "224.0.0.251:5353";
+const MDNS_SERVICE: [&str; 3] = ["_hue", "_tcp", "local"];
+const DNS_TYPE_PTR: u16 = 12;
+const DNS_TYPE_TXT: u16 = 16;
+const DNS_CLASS_IN_QU: u16 = 0x8001;
+
+const SSDP_ADDR: &str = "239.255.255.250:1900";
+const SSDP_SEARCH: &str = "M-SEARCH * HTTP/1.1\r\n\
+    HOST: 239.255.255.250:1900\r\n\
+    MAN: \"ssdp:discover\"\r\n\
+    MX: 2\r\n\
+    ST: ssdp:all\r\n\r\n";
+
+/// Looks for Hue bridges on the local network using both mDNS and SSDP,
+/// returning the id and address of the first bridge matching `bridge_id`
+/// (or any bridge when `None`) to answer within `timeout`.
+pub async fn discover_bridge(
+    bridge_id: Option<&str>,
+    timeout: time::Duration,
+) -> Result<Option<(String, IpAddr)>> {
+    let mdns = UdpSocket::bind("0.0.0.0:0").await?;
+    mdns.send_to(&mdns_query(), MDNS_ADDR.parse::<SocketAddr>()?)
+        .await?;
+
+    let ssdp = UdpSocket::bind("0.0.0.0:0").await?;
+    ssdp.send_to(SSDP_SEARCH.as_bytes(), SSDP_ADDR.parse::<SocketAddr>()?)
+        .await?;
+
+    let deadline = time::sleep(timeout);
+    tokio::pin!(deadline);
+
+    let mut mdns_buf = [0; 1500];
+    let mut ssdp_buf = [0; 1500];
+    loop {
+        let found = tokio::select! {
+            _ = &mut deadline => return Ok(None),
+            Ok((len, src)) = mdns.recv_from(&mut mdns_buf) => {
+                mdns_bridge_id(&mdns_buf[..len]).map(|id| (id, src.ip()))
+            }
+            Ok((len, src)) = ssdp.recv_from(&mut ssdp_buf) => {
+                ssdp_bridge_id(&ssdp_buf[..len]).map(|id| (id, src.ip()))
+            }
+        };
+
+        match found {
+            Some((id, ip)) if bridge_id.iter().all(|want| want.eq_ignore_ascii_case(&id)) => {
+                println!("found hue bridge {} at {}", id, ip);
+                return Ok(Some((id, ip)));
+            }
+            _ => (),
+        }
+    }
+}
+
+fn mdns_query() -> Vec<u8> {
+    // id, flags, one question, no answer/authority/additional records
+    let mut query = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in MDNS_SERVICE {
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&DNS_TYPE_PTR.to_be_bytes());
+    query.extend_from_slice(&DNS_CLASS_IN_QU.to_be_bytes());
+    query
+}
+
+fn skip_name(buf: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        match *buf.get(pos)? as usize {
+            0 => return Some(pos + 1),
+            len if len & 0xC0 == 0xC0 => return Some(pos + 2),
+            len => pos += len + 1,
+        }
+    }
+}
+
+fn mdns_bridge_id(buf: &[u8]) -> Option<String> {
+    let u16_at = |pos: usize| Some(u16::from_be_bytes([*buf.get(pos)?, *buf.get(pos + 1)?]));
+
+    let questions = u16_at(4)?;
+    let records = [6, 8, 10]
+        .iter()
+        .map(|&pos| u16_at(pos).map(usize::from))
+        .sum::<Option<usize>>()?;
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(buf, pos)? + 4;
+    }
+    for _ in 0..records {
+        pos = skip_name(buf, pos)?;
+        let rtype = u16_at(pos)?;
+        let rdlen = u16_at(pos + 8)? as usize;
+        let mut rdata = buf.get(pos + 10..pos + 10 + rdlen)?;
+        pos += 10 + rdlen;
+
+        if rtype != DNS_TYPE_TXT {
+            continue;
+        }
+        while let Some((&len, rest)) = rdata.split_first() {
+            let entry = rest.get(..len as usize)?;
+            if let Some(id) = entry.strip_prefix(b"bridgeid=") {
+                return String::from_utf8(id.to_vec()).ok();
+            }
+            rdata = &rest[len as usize..];
+        }
+    }
+    None
+}
+
+fn ssdp_bridge_id(buf: &[u8]) -> Option<String> {
+    std::str::from_utf8(buf).ok()?.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("hue-bridgeid")
+            .then(|| String::from(value.trim()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A bridge's answer to `mdns_query`: the PTR for its instance and a TXT
+    /// record carrying the bridge id.
+    const MDNS_RESPONSE: &[u8] = b"\x00\x00\x84\x00\x00\x00\x00\x01\x00\x00\x00\x01\
+        \x04_hue\x04_tcp\x05local\x00\x00\x0c\x00\x01\x00\x00\x00x\x00\x17\
+        \x14Philips Hue - 685A3F\xc0\x0c\
+        \xc0'\x00\x10\x80\x01\x00\x00\x00x\x00)\
+        \x19bridgeid=001788fffe685a3f\x0emodelid=BSB002";
+
+    const SSDP_RESPONSE: &str = "HTTP/1.1 200 OK\r\n\
+        HOST: 239.255.255.250:1900\r\n\
+        EXT:\r\n\
+        CACHE-CONTROL: max-age=100\r\n\
+        LOCATION: http://192.168.1.2:80/description.xml\r\n\
+        SERVER: Hue/1.0 UPnP/1.0 IpBridge/1.56.0\r\n\
+        hue-bridgeid: 001788FFFE685A3F\r\n\
+        ST: upnp:rootdevice\r\n\
+        USN: uuid:2f402f80-da50-11e1-9b23-001788685a3f::upnp:rootdevice\r\n\r\n";
+
+    #[test]
+    fn builds_mdns_query() {
+        let query = mdns_query();
+        assert_eq!(query[..12], [0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&query[12..29], b"\x04_hue\x04_tcp\x05local\x00");
+        assert_eq!(query[29..], [0, 12, 0x80, 1]);
+    }
+
+    #[test]
+    fn parses_mdns_response() {
+        assert_eq!(
+            mdns_bridge_id(MDNS_RESPONSE).as_deref(),
+            Some("001788fffe685a3f")
+        );
+    }
+
+    #[test]
+    fn ignores_mdns_without_bridge_id() {
+        assert_eq!(mdns_bridge_id(&mdns_query()), None);
+        assert_eq!(mdns_bridge_id(&[]), None);
+    }
+
+    #[test]
+    fn rejects_malformed_mdns() {
+        for len in 0..MDNS_RESPONSE.len() {
+            assert_eq!(mdns_bridge_id(&MDNS_RESPONSE[..len]), None, "{} bytes", len);
+        }
+
+        let mut counts = MDNS_RESPONSE.to_vec();
+        counts[4..12].fill(0xff);
+        assert_eq!(mdns_bridge_id(&counts), None);
+
+        // TXT entry claiming to run past the end of its record
+        let mut entry = MDNS_RESPONSE.to_vec();
+        let pos = entry.len() - 15 - 26;
+        entry[pos] = 0x7f;
+        assert_eq!(mdns_bridge_id(&entry), None);
+    }
+
+    #[test]
+    fn parses_ssdp_response() {
+        assert_eq!(
+            ssdp_bridge_id(SSDP_RESPONSE.as_bytes()).as_deref(),
+            Some("001788FFFE685A3F")
+        );
+    }
+
+    #[test]
+    fn ignores_ssdp_without_bridge_id() {
+        let other = SSDP_RESPONSE.replace("hue-bridgeid", "x-other");
+        assert_eq!(ssdp_bridge_id(other.as_bytes()), None);
+        assert_eq!(ssdp_bridge_id(b"\xff\xfe hue-bridgeid: 1"), None);
+        assert_eq!(ssdp_bridge_id(b"hue-bridgeid"), None);
+    }
+}
@@ -0,0 +1,171 @@
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ServerName};
+use sha2::{Digest, Sha256};
+
+use crate::Result;
+
+/// Signify's bridge root CA, as published in the Hue developer documentation.
+const HUE_CA_CERT: &[u8] = include_bytes!("huebridge_cacert.pem");
+
+static SIGNATURE_ALGORITHMS: &[&webpki::SignatureAlgorithm] = &[
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::ECDSA_P384_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+];
+
+/// Fingerprint of the bridge certificate a client is pinned to, set by the
+/// first handshake of a client that trusts on first use.
+pub(crate) type PinnedCertificate = Arc<Mutex<Option<String>>>;
+
+/// Bridges present a certificate for their bridge id rather than their
+/// address, so the chain is checked against the Signify CA and the common name
+/// against the bridge id. Older bridges with self-signed certificates fail the
+/// chain check and are matched against the pinned fingerprint instead. With
+/// nothing pinned they're rejected, unless the client trusts on first use, in
+/// which case the first certificate seen is pinned for every later handshake.
+struct HueVerifier {
+    bridge_id: Option<String>,
+    pinned: PinnedCertificate,
+    trust_on_first_use: bool,
+    roots: Vec<Vec<u8>>,
+}
+
+impl HueVerifier {
+    fn verify_chain(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        now: SystemTime,
+    ) -> std::result::Result<(), rustls::Error> {
+        let invalid = |e: webpki::Error| {
+            rustls::Error::General(format!("invalid bridge certificate: {:?}", e))
+        };
+
+        let anchors = self
+            .roots
+            .iter()
+            .map(|der| webpki::TrustAnchor::try_from_cert_der(der))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(invalid)?;
+        let chain: Vec<&[u8]> = intermediates.iter().map(|c| c.0.as_slice()).collect();
+
+        webpki::EndEntityCert::try_from(end_entity.0.as_slice())
+            .map_err(invalid)?
+            .verify_is_valid_tls_server_cert(
+                SIGNATURE_ALGORITHMS,
+                &webpki::TlsServerTrustAnchors(&anchors),
+                &chain,
+                webpki::Time::try_from(now).map_err(|_| invalid(webpki::Error::BadDerTime))?,
+            )
+            .map_err(invalid)
+    }
+
+    fn verify_bridge_id(&self, end_entity: &Certificate) -> std::result::Result<(), rustls::Error> {
+        let bridge_id = match &self.bridge_id {
+            Some(bridge_id) => bridge_id,
+            None => return Ok(()),
+        };
+
+        let (_, cert) = x509_parser::parse_x509_certificate(&end_entity.0)
+            .map_err(|e| rustls::Error::General(format!("invalid bridge certificate: {:?}", e)))?;
+        let common_name = cert
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok());
+
+        match common_name {
+            Some(cn) if cn.eq_ignore_ascii_case(bridge_id) => Ok(()),
+            _ => Err(rustls::Error::General(format!(
+                "bridge certificate {:?} does not match bridge id {}",
+                common_name, bridge_id
+            ))),
+        }
+    }
+}
+
+impl ServerCertVerifier for HueVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        let fingerprint = fingerprint(&end_entity.0);
+        let mut pinned = self.pinned.lock().unwrap();
+
+        let chain = match self.verify_chain(end_entity, intermediates, now) {
+            Ok(()) => {
+                self.verify_bridge_id(end_entity)?;
+                return Ok(ServerCertVerified::assertion());
+            }
+            Err(e) => e,
+        };
+
+        match pinned.as_deref() {
+            Some(pin) if pin.eq_ignore_ascii_case(&fingerprint) => {
+                Ok(ServerCertVerified::assertion())
+            }
+            Some(pin) => Err(rustls::Error::General(format!(
+                "{} and bridge certificate {} does not match pinned {}",
+                chain, fingerprint, pin
+            ))),
+            None if self.trust_on_first_use => {
+                println!(
+                    "WARN: {}, trusting hue bridge certificate {} on first use",
+                    chain, fingerprint
+                );
+                *pinned = Some(fingerprint);
+                Ok(ServerCertVerified::assertion())
+            }
+            None => Err(rustls::Error::General(format!(
+                "{} and no certificate is pinned, pair with the bridge again",
+                chain
+            ))),
+        }
+    }
+}
+
+fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn load_roots() -> Result<Vec<Vec<u8>>> {
+    Ok(rustls_pemfile::certs(&mut &HUE_CA_CERT[..])?)
+}
+
+/// Builds a client for REST calls to a bridge, returning it alongside the
+/// fingerprint it is pinned to, which a client that trusts on first use fills
+/// in during its first handshake.
+pub(crate) fn client(
+    bridge_id: Option<&str>,
+    pinned: Option<&str>,
+    trust_on_first_use: bool,
+) -> Result<(reqwest::Client, PinnedCertificate)> {
+    let pinned = PinnedCertificate::new(Mutex::new(pinned.map(String::from)));
+    let verifier = HueVerifier {
+        bridge_id: bridge_id.map(String::from),
+        pinned: Arc::clone(&pinned),
+        trust_on_first_use,
+        roots: load_roots()?,
+    };
+    let tls = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    let client = reqwest::Client::builder()
+        .use_preconfigured_tls(tls)
+        .build()?;
+    Ok((client, pinned))
+}
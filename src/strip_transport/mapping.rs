@@ -0,0 +1,224 @@
+use std::ops::Range;
+use std::str::FromStr;
+
+use smart_leds::RGB8;
+
+use super::StripTransport;
+use crate::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Forward(Range<usize>),
+    Reversed(Range<usize>),
+    /// Dark leds covering a physical break in the strip.
+    Gap(usize),
+}
+
+/// Which frame pixel drives each led of a transport: the segments are
+/// concatenated, the result repeated, mirrored and finally rotated by `offset`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Mapping {
+    segments: Vec<Segment>,
+    repeat: usize,
+    mirror: bool,
+    offset: isize,
+}
+
+impl Mapping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn segment(mut self, range: Range<usize>) -> Self {
+        self.segments.push(Segment::Forward(range));
+        self
+    }
+
+    pub fn reversed(mut self, range: Range<usize>) -> Self {
+        self.segments.push(Segment::Reversed(range));
+        self
+    }
+
+    pub fn gap(mut self, leds: usize) -> Self {
+        self.segments.push(Segment::Gap(leds));
+        self
+    }
+
+    /// Plays the segments `times` times in a row.
+    pub fn repeat(mut self, times: usize) -> Self {
+        self.repeat = times;
+        self
+    }
+
+    /// Follows the segments with a reversed copy of themselves.
+    pub fn mirror(mut self) -> Self {
+        self.mirror = true;
+        self
+    }
+
+    /// Starts the output `offset` leds into the pattern, wrapping around.
+    pub fn offset(mut self, offset: isize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Frame index for each output led, `None` for gaps.
+    fn indices(&self) -> Vec<Option<usize>> {
+        let pattern = self
+            .segments
+            .iter()
+            .flat_map(|segment| -> Box<dyn Iterator<Item = Option<usize>>> {
+                match segment {
+                    Segment::Forward(r) => Box::new(r.clone().map(Some)),
+                    Segment::Reversed(r) => Box::new(r.clone().rev().map(Some)),
+                    Segment::Gap(leds) => Box::new(vec![None; *leds].into_iter()),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut indices = pattern.repeat(self.repeat.max(1));
+        if self.mirror {
+            let reversed = indices.iter().rev().copied().collect::<Vec<_>>();
+            indices.extend(reversed);
+        }
+        if !indices.is_empty() {
+            let offset = self.offset.rem_euclid(indices.len() as isize) as usize;
+            indices.rotate_left(offset);
+        }
+        indices
+    }
+}
+
+/// Parses a comma separated spec such as `0..30,r30..60,gap:4,mirror,offset:5`.
+impl FromStr for Mapping {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = |part: &str| Error::ConfigError(format!("invalid mapping {:?}", part));
+        let number = |part: &str, value: &str| value.parse().map_err(|_| invalid(part));
+        let range = |part: &str, value: &str| -> Result<Range<usize>> {
+            let (start, end) = value.split_once("..").ok_or_else(|| invalid(part))?;
+            Ok(number(part, start)?..number(part, end)?)
+        };
+
+        s.split(',')
+            .map(str::trim)
+            .try_fold(Mapping::new(), |mapping, part| {
+                Ok(match part.split_once(':') {
+                    Some(("gap", n)) => mapping.gap(number(part, n)?),
+                    Some(("repeat", n)) => mapping.repeat(number(part, n)?),
+                    Some(("offset", n)) => mapping.offset(n.parse().map_err(|_| invalid(part))?),
+                    Some(_) => return Err(invalid(part)),
+                    None if part == "mirror" => mapping.mirror(),
+                    None => match part.strip_prefix('r') {
+                        Some(r) => mapping.reversed(range(part, r)?),
+                        None => mapping.segment(range(part, part)?),
+                    },
+                })
+            })
+    }
+}
+
+pub struct MappedStripTransport {
+    pub base: Box<StripTransport>,
+    pub(super) mapping: Mapping,
+    indices: Vec<Option<usize>>,
+}
+
+impl MappedStripTransport {
+    pub(super) fn new(base: StripTransport, mapping: Mapping) -> Result<Self> {
+        let indices = mapping.indices();
+        if indices.is_empty() {
+            return Err(Error::ConfigError(format!(
+                "{:?} maps no leds of {:?}",
+                mapping, base
+            )));
+        }
+        Ok(MappedStripTransport {
+            base: Box::new(base),
+            mapping,
+            indices,
+        })
+    }
+
+    /// Number of leds written to the base transport.
+    pub(super) fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// One past the highest frame index read, 0 if only gaps are mapped.
+    pub(super) fn source_len(&self) -> usize {
+        self.indices.iter().flatten().max().map_or(0, |i| i + 1)
+    }
+
+    pub(super) async fn write(&mut self, frame: &[RGB8]) -> Result<()> {
+        let mapped = self
+            .indices
+            .iter()
+            .map(|idx| idx.and_then(|i| frame.get(i).copied()).unwrap_or_default())
+            .collect::<Vec<_>>();
+        self.base.write_frame(&mapped).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segments_and_gaps_in_order() {
+        let mapping = Mapping::new().segment(0..2).gap(2).reversed(4..7);
+        assert_eq!(
+            mapping.indices(),
+            [Some(0), Some(1), None, None, Some(6), Some(5), Some(4)]
+        );
+    }
+
+    #[test]
+    fn repeat_then_mirror() {
+        let mapping = Mapping::new().segment(0..2).repeat(2).mirror();
+        assert_eq!(mapping.indices(), [0, 1, 0, 1, 1, 0, 1, 0].map(Some));
+    }
+
+    #[test]
+    fn offset_wraps_both_ways() {
+        let mapping = Mapping::new().segment(0..4);
+        assert_eq!(mapping.clone().offset(1).indices(), [1, 2, 3, 0].map(Some));
+        assert_eq!(mapping.clone().offset(-1).indices(), [3, 0, 1, 2].map(Some));
+        assert_eq!(mapping.offset(-9).indices(), [3, 0, 1, 2].map(Some));
+    }
+
+    #[test]
+    fn parses_spec() {
+        let parsed = "0..30, r30..60,gap:4,repeat:2,mirror,offset:-5"
+            .parse::<Mapping>()
+            .unwrap();
+        let built = Mapping::new()
+            .segment(0..30)
+            .reversed(30..60)
+            .gap(4)
+            .repeat(2)
+            .mirror()
+            .offset(-5);
+        assert_eq!(parsed, built);
+    }
+
+    #[test]
+    fn rejects_invalid_spec() {
+        for spec in [
+            "",
+            "0..",
+            "..5",
+            "r5",
+            "0-30",
+            "gap:",
+            "gap:-1",
+            "repeat:x",
+            "offset:1.5",
+            "skip:2",
+            "mirrored",
+        ] {
+            assert!(spec.parse::<Mapping>().is_err(), "{:?}", spec);
+        }
+    }
+}
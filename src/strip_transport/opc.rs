@@ -0,0 +1,108 @@
+use std::net::SocketAddr;
+
+use rgb::RGB8;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+use tokio::time::{self, Instant};
+
+use super::{AsyncSmartLedsWrite, FrameSink};
+use crate::{Error, Result};
+use async_trait::async_trait;
+
+const OPC_HEADER_LEN: usize = 4;
+const OPC_CMD_SET_PIXELS: u8 = 0;
+const OPC_RECONNECT_INTERVAL: time::Duration = time::Duration::from_secs(1);
+
+/// Open Pixel Control client, frames are handed to a background task that
+/// owns the connection so a slow or absent server never blocks the caller.
+/// Only the latest frame is kept, anything not yet sent is dropped.
+pub struct OpcStrip {
+    pub(crate) dest: SocketAddr,
+    pub(crate) channel: u8,
+    frames: watch::Sender<Vec<u8>>,
+}
+
+impl OpcStrip {
+    pub(crate) fn new(dest: SocketAddr, channel: u8) -> Self {
+        let (frames, rx) = watch::channel(Vec::new());
+        tokio::spawn(Self::run(dest, rx));
+        OpcStrip {
+            dest,
+            channel,
+            frames,
+        }
+    }
+
+    async fn run(dest: SocketAddr, mut frames: watch::Receiver<Vec<u8>>) {
+        let mut stream: Option<TcpStream> = None;
+        let mut retry_at = Instant::now();
+
+        while frames.changed().await.is_ok() {
+            if stream.is_none() && Instant::now() >= retry_at {
+                match TcpStream::connect(dest).await {
+                    Ok(s) => {
+                        s.set_nodelay(true).ok();
+                        println!("connected to opc:{:?}", dest);
+                        stream = Some(s);
+                    }
+                    Err(e) => {
+                        println!("WARN: failed to connect to opc:{:?}: {:?}", dest, e);
+                        retry_at = Instant::now() + OPC_RECONNECT_INTERVAL;
+                    }
+                }
+            }
+
+            if let Some(s) = stream.as_mut() {
+                let frame = frames.borrow_and_update().clone();
+                if let Err(e) = s.write_all(&frame).await {
+                    println!("WARN: disconnected from opc:{:?}: {:?}", dest, e);
+                    stream = None;
+                    retry_at = Instant::now() + OPC_RECONNECT_INTERVAL;
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncSmartLedsWrite for OpcStrip {
+    type Error = Error;
+    type Color = RGB8;
+
+    async fn write<T, I>(&mut self, iterator: T) -> Result<()>
+    where
+        T: Iterator<Item = I> + Send,
+        I: Into<Self::Color>,
+    {
+        let mut frame = vec![self.channel, OPC_CMD_SET_PIXELS, 0, 0];
+        frame.extend(iterator.flat_map(|item| {
+            let i = item.into();
+            [i.r, i.g, i.b]
+        }));
+
+        let len = (frame.len() - OPC_HEADER_LEN).min(u16::MAX as usize) as u16;
+        frame[2..4].copy_from_slice(&len.to_be_bytes());
+        frame.truncate(OPC_HEADER_LEN + len as usize);
+
+        self.frames.send_replace(frame);
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for OpcStrip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "opc:{:?}/{}", self.dest, self.channel)
+    }
+}
+
+#[async_trait]
+impl FrameSink for OpcStrip {
+    fn kind(&self) -> &'static str {
+        "opc"
+    }
+
+    async fn write_frame(&mut self, frame: &[RGB8]) -> Result<()> {
+        AsyncSmartLedsWrite::write(self, frame.iter().copied()).await
+    }
+}
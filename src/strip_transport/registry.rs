@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::str::FromStr;
+
+use futures::future::BoxFuture;
+use tokio::time;
+
+use super::{FrameSink, HealthPolicy, Mapping, Refresh, Resample, StripTransport};
+use crate::{Error, Result};
+
+/// Settings for a single output, as key/value pairs from a config.
+pub type TransportOptions = HashMap<String, String>;
+
+pub type TransportFactory =
+    for<'a> fn(&'a TransportOptions) -> BoxFuture<'a, Result<Box<dyn FrameSink>>>;
+
+/// Transport factories keyed by type name. The default registry holds every
+/// output compiled into this crate, others can be added with `register`.
+pub struct TransportRegistry {
+    factories: HashMap<&'static str, TransportFactory>,
+}
+
+impl TransportRegistry {
+    pub fn new() -> Self {
+        TransportRegistry {
+            factories: HashMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, kind: &'static str, factory: TransportFactory) {
+        self.factories.insert(kind, factory);
+    }
+
+    pub fn kinds(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.factories.keys().copied()
+    }
+
+    /// Builds a `kind` transport, [configured](StripTransport::configure) by
+    /// the same `options` its factory reads.
+    pub async fn create(&self, kind: &str, options: &TransportOptions) -> Result<StripTransport> {
+        let factory = self
+            .factories
+            .get(kind)
+            .ok_or_else(|| Error::ConfigError(format!("unknown transport {}", kind)))?;
+        StripTransport::Sink(factory(options).await?).configure(options)
+    }
+}
+
+impl StripTransport {
+    /// Applies the options shared by every transport: it is mapped by the
+    /// `mapping` option if present and sampled by `sample` (e.g. `30..75:15`)
+    /// using the `resample` mode. `max-fps`, `keepalive` and `delay` (in
+    /// seconds) override the [`Refresh`] of its leaves, `critical`,
+    /// `fail-after` and `retry` (in seconds) their [`HealthPolicy`].
+    pub fn configure(self, options: &TransportOptions) -> Result<Self> {
+        let refresh = Refresh {
+            max_fps: option(options, "max-fps")?,
+            keepalive: seconds(options, "keepalive")?,
+            delay: seconds(options, "delay")?,
+        };
+        let defaults = HealthPolicy::default();
+        let policy = HealthPolicy {
+            critical: option(options, "critical")?.unwrap_or(defaults.critical),
+            fail_after: option(options, "fail-after")?.unwrap_or(defaults.fail_after),
+            retry: seconds(options, "retry")?.unwrap_or(defaults.retry),
+            ..defaults
+        };
+        let transport = self.guard(policy).with_refresh(refresh)?;
+        let transport = match option::<Mapping>(options, "mapping")? {
+            Some(mapping) => transport.map(mapping)?,
+            None => transport,
+        };
+        match (sample(options)?, option::<Resample>(options, "resample")?) {
+            (Some((range, count)), resample) => {
+                transport.sample_with(range, count, resample.unwrap_or_default())
+            }
+            (None, Some(_)) => Err(Error::ConfigError(String::from("resample without sample"))),
+            (None, None) => Ok(transport),
+        }
+    }
+}
+
+impl Default for TransportRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        #[cfg(feature = "spi")]
+        registry.register("ws2812", ws2812);
+        #[cfg(feature = "hue")]
+        registry.register("hue", hue);
+        registry.register("udp", udp);
+        registry.register("ddp", ddp);
+        registry.register("opc", opc);
+        #[cfg(feature = "debug-image")]
+        registry.register("debug-image", debug_image);
+        registry
+    }
+}
+
+fn option<T: FromStr>(options: &TransportOptions, key: &str) -> Result<Option<T>> {
+    options
+        .get(key)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| Error::ConfigError(format!("invalid {} {:?}", key, value)))
+        })
+        .transpose()
+}
+
+fn seconds(options: &TransportOptions, key: &str) -> Result<Option<time::Duration>> {
+    option(options, key)?
+        .map(time::Duration::try_from_secs_f64)
+        .transpose()
+        .map_err(|e| Error::ConfigError(format!("invalid {}: {}", key, e)))
+}
+
+/// Parses the `sample` option, a frame range and the led count it maps onto.
+fn sample(options: &TransportOptions) -> Result<Option<(Range<usize>, usize)>> {
+    let value = match options.get("sample") {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let invalid = || Error::ConfigError(format!("invalid sample {:?}", value));
+    let (range, count) = value.split_once(':').ok_or_else(invalid)?;
+    let (start, end) = range.split_once("..").ok_or_else(invalid)?;
+    let number = |n: &str| n.trim().parse().map_err(|_| invalid());
+    Ok(Some((number(start)?..number(end)?, number(count)?)))
+}
+
+fn required<T: FromStr>(options: &TransportOptions, key: &str) -> Result<T> {
+    option(options, key)?.ok_or_else(|| Error::ConfigError(format!("missing {}", key)))
+}
+
+#[cfg(feature = "spi")]
+fn ws2812(_: &TransportOptions) -> BoxFuture<'_, Result<Box<dyn FrameSink>>> {
+    Box::pin(async move { Ok(Box::new(super::spi::Ws2812Strip::new()?) as Box<dyn FrameSink>) })
+}
+
+#[cfg(feature = "hue")]
+fn hue(options: &TransportOptions) -> BoxFuture<'_, Result<Box<dyn FrameSink>>> {
+    use super::{Hue, HueArea, HueColorSpace, HueCredentials};
+
+    Box::pin(async move {
+        let hub_ip: String = required(options, "hub")?;
+        let credentials = HueCredentials::load(&required::<String>(options, "credentials")?)?;
+        let area = match (option(options, "group")?, option(options, "entertainment")?) {
+            (Some(group), _) => HueArea::Group(group),
+            (None, Some(id)) => HueArea::Entertainment(id),
+            (None, None) => {
+                return Err(Error::ConfigError(String::from(
+                    "missing group or entertainment",
+                )))
+            }
+        };
+        let color_space = option(options, "color-space")?.unwrap_or(HueColorSpace::Rgb);
+
+        let mut hue = Hue::new(&hub_ip, &credentials, area, color_space).await?;
+        hue.set_positional(option(options, "positional")?.unwrap_or(false));
+        Ok(Box::new(hue) as Box<dyn FrameSink>)
+    })
+}
+
+fn udp(options: &TransportOptions) -> BoxFuture<'_, Result<Box<dyn FrameSink>>> {
+    use super::UdpMode;
+
+    Box::pin(async move {
+        let dest: SocketAddr = required(options, "dest")?;
+        let mode = option(options, "mode")?.unwrap_or(UdpMode::Drgb);
+        let timeout = option(options, "timeout")?.unwrap_or(5);
+        Ok(
+            Box::new(super::udpstrip::UdpStrip::new(dest, mode, timeout).await?)
+                as Box<dyn FrameSink>,
+        )
+    })
+}
+
+fn ddp(options: &TransportOptions) -> BoxFuture<'_, Result<Box<dyn FrameSink>>> {
+    Box::pin(async move {
+        let dest: SocketAddr = required(options, "dest")?;
+        Ok(Box::new(super::ddp::DdpStrip::new(dest).await?) as Box<dyn FrameSink>)
+    })
+}
+
+fn opc(options: &TransportOptions) -> BoxFuture<'_, Result<Box<dyn FrameSink>>> {
+    Box::pin(async move {
+        let dest: SocketAddr = required(options, "dest")?;
+        let channel = option(options, "channel")?.unwrap_or(0);
+        Ok(Box::new(super::opc::OpcStrip::new(dest, channel)) as Box<dyn FrameSink>)
+    })
+}
+
+#[cfg(feature = "debug-image")]
+fn debug_image(options: &TransportOptions) -> BoxFuture<'_, Result<Box<dyn FrameSink>>> {
+    Box::pin(async move {
+        let width = required(options, "width")?;
+        let height = required(options, "height")?;
+        Ok(Box::new(super::dbgimg::DebugImage::new(width, height)) as Box<dyn FrameSink>)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(pairs: &[(&str, &str)]) -> TransportOptions {
+        pairs
+            .iter()
+            .map(|(k, v)| (String::from(*k), String::from(*v)))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn samples_with_resample_mode() {
+        let registry = TransportRegistry::default();
+        let transport = registry
+            .create(
+                "opc",
+                &options(&[
+                    ("dest", "127.0.0.1:7890"),
+                    ("sample", "30..75:15"),
+                    ("resample", "gaussian"),
+                ]),
+            )
+            .await
+            .unwrap();
+        match transport {
+            StripTransport::Sampled(s) => {
+                assert_eq!(
+                    (s.range, s.count, s.resample),
+                    (30..75, 15, Resample::Gaussian)
+                )
+            }
+            other => panic!("not sampled: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn refresh_after_spawn_is_an_error() {
+        let options = options(&[("dest", "127.0.0.1:7890"), ("max-fps", "30")]);
+        let transport = TransportRegistry::default()
+            .create("opc", &options)
+            .await
+            .unwrap();
+        let refresh = Refresh {
+            max_fps: Some(50.0),
+            ..Refresh::default()
+        };
+        assert!(transport.spawn().with_refresh(refresh).is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_sample() {
+        let registry = TransportRegistry::default();
+        for sample in [
+            &[("sample", "30..75")][..],
+            &[("sample", "30:15")],
+            &[("sample", "75..30:15")],
+            &[("sample", "30..75:15"), ("resample", "cubic")],
+            &[("resample", "box")],
+        ] {
+            let mut options = options(sample);
+            options.insert(String::from("dest"), String::from("127.0.0.1:7890"));
+            assert!(
+                registry.create("opc", &options).await.is_err(),
+                "{:?}",
+                sample
+            );
+        }
+    }
+}
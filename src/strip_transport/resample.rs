@@ -0,0 +1,194 @@
+use std::ops::Range;
+use std::str::FromStr;
+
+use palette::{LinSrgb, Srgb};
+use smart_leds::RGB8;
+
+use crate::{Error, Result};
+
+/// How a sampled transport computes each of its leds from the frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Resample {
+    /// The frame pixel under the led's centre.
+    Nearest,
+    /// Interpolates between the two frame pixels either side of the led's centre.
+    Linear,
+    /// Averages the frame pixels the led covers, weighted by how much of each.
+    #[default]
+    Box,
+    /// Gaussian weighted average around the led's centre, smoother than `Box`.
+    Gaussian,
+}
+
+impl FromStr for Resample {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "nearest" => Ok(Resample::Nearest),
+            "linear" => Ok(Resample::Linear),
+            "box" => Ok(Resample::Box),
+            "gaussian" => Ok(Resample::Gaussian),
+            _ => Err(Error::ConfigError(format!("unknown resampling {}", s))),
+        }
+    }
+}
+
+/// Resamples `range` of the frame to `leds` pixels, up or down, blending in
+/// linear light.
+pub fn scale<T, I>(iterator: T, range: Range<usize>, leds: usize, mode: Resample) -> Vec<[u8; 3]>
+where
+    T: Iterator<Item = I>,
+    I: Into<RGB8>,
+{
+    let src = iterator
+        .skip(range.start)
+        .take(range.len())
+        .map(|x| {
+            let i = x.into();
+            Srgb::new(i.r, i.g, i.b).into_format::<f32>().into_linear()
+        })
+        .collect::<Vec<LinSrgb>>();
+    if src.is_empty() {
+        return vec![[0; 3]; leds];
+    }
+
+    let n = src.len();
+    let step = n as f32 / leds as f32;
+    let last = (n - 1) as f32;
+    (0..leds)
+        .map(|i| {
+            let start = i as f32 * step;
+            let end = start + step;
+            let centre = (start + end) / 2.0 - 0.5;
+
+            let out = match mode {
+                Resample::Nearest => src[(centre.round().max(0.0) as usize).min(n - 1)],
+                Resample::Linear => {
+                    let c = centre.clamp(0.0, last);
+                    let (lo, hi) = (c.floor() as usize, c.ceil() as usize);
+                    blend(&src, [(lo, 1.0 - c.fract()), (hi, c.fract())])
+                }
+                Resample::Box => {
+                    let first = start.floor() as usize;
+                    let weights = (first..(end.ceil() as usize).min(n)).map(|j| {
+                        let overlap = end.min(j as f32 + 1.0) - start.max(j as f32);
+                        (j, overlap.max(0.0))
+                    });
+                    blend(&src, weights)
+                }
+                Resample::Gaussian => {
+                    let sigma = step.max(1.0) / 2.0;
+                    let reach = (3.0 * sigma).ceil();
+                    let lo = (centre - reach).max(0.0) as usize;
+                    let hi = ((centre + reach).min(last) as usize).max(lo);
+                    let weights = (lo..=hi).map(|j| {
+                        let d = j as f32 - centre;
+                        (j, (-d * d / (2.0 * sigma * sigma)).exp())
+                    });
+                    blend(&src, weights)
+                }
+            };
+
+            let out: Srgb<u8> = Srgb::from_linear(out).into_format();
+            [out.red, out.green, out.blue]
+        })
+        .collect()
+}
+
+/// Normalised weighted sum of `src` pixels given as `(index, weight)`.
+fn blend(src: &[LinSrgb], weights: impl IntoIterator<Item = (usize, f32)>) -> LinSrgb {
+    let (sum, total) = weights.into_iter().fold(
+        (LinSrgb::new(0.0, 0.0, 0.0), 0.0),
+        |(sum, total), (j, w)| (sum + src[j] * w, total + w),
+    );
+    match total > 0.0 {
+        true => sum / total,
+        false => sum,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: RGB8 = RGB8 { r: 0, g: 0, b: 0 };
+    const WHITE: RGB8 = RGB8 {
+        r: 255,
+        g: 255,
+        b: 255,
+    };
+
+    fn grey(frame: &[RGB8], range: Range<usize>, leds: usize, mode: Resample) -> Vec<u8> {
+        scale(frame.iter().copied(), range, leds, mode)
+            .into_iter()
+            .map(|[r, g, b]| {
+                assert!(r == g && g == b);
+                r
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nearest_picks_centre_pixel() {
+        let frame = (0..6).map(|i| RGB8::new(i, i, i)).collect::<Vec<_>>();
+        assert_eq!(grey(&frame, 0..6, 3, Resample::Nearest), [1, 3, 5]);
+        assert_eq!(grey(&frame, 2..4, 4, Resample::Nearest), [2, 2, 3, 3]);
+    }
+
+    #[test]
+    fn linear_interpolates_when_upsampling() {
+        let out = grey(&[BLACK, WHITE], 0..2, 4, Resample::Linear);
+        assert_eq!(out[0], 0);
+        assert!(0 < out[1] && out[1] < out[2] && out[2] < 255);
+        assert_eq!(out[3], 255);
+    }
+
+    #[test]
+    fn box_averages_covered_pixels() {
+        let frame = [BLACK, WHITE, BLACK, WHITE];
+        let out = grey(&frame, 0..4, 2, Resample::Box);
+        assert_eq!(out[0], out[1]);
+        // half white in linear light
+        assert!((187..=188).contains(&out[0]), "{:?}", out);
+        assert_eq!(grey(&frame, 0..4, 4, Resample::Box), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn box_splits_remainder_pixels() {
+        let frame = [BLACK, BLACK, WHITE, BLACK, BLACK];
+        let out = grey(&frame, 0..5, 2, Resample::Box);
+        assert_eq!(out[0], out[1]);
+        assert!(0 < out[0] && out[0] < 255);
+
+        let frame = [BLACK, BLACK, BLACK, BLACK, WHITE];
+        let out = grey(&frame, 0..5, 2, Resample::Box);
+        assert_eq!(out[0], 0);
+        assert!(0 < out[1] && out[1] < 255);
+    }
+
+    #[test]
+    fn gaussian_smooths_neighbours() {
+        let out = grey(&[BLACK, WHITE, BLACK], 0..3, 3, Resample::Gaussian);
+        assert_eq!(out[0], out[2]);
+        assert!(0 < out[0] && out[0] < out[1] && out[1] < 255);
+        assert_eq!(
+            grey(&[WHITE; 5], 0..5, 3, Resample::Gaussian),
+            [255, 255, 255]
+        );
+    }
+
+    #[test]
+    fn range_past_frame_end() {
+        let frame = [BLACK, BLACK, WHITE];
+        for mode in [
+            Resample::Nearest,
+            Resample::Linear,
+            Resample::Box,
+            Resample::Gaussian,
+        ] {
+            assert_eq!(grey(&frame, 2..10, 3, mode), [255, 255, 255]);
+            assert_eq!(grey(&frame, 5..10, 2, mode), [0, 0]);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::sync::{Arc, Mutex};
+
+use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
+use smart_leds::{SmartLedsWrite, RGB8};
+use ws2812_spi::hosted::Ws2812;
+
+use super::FrameSink;
+use crate::Result;
+use async_trait::async_trait;
+
+/// WS2812 strip on the Raspberry Pi's SPI0 bus. Writes block, so they are
+/// done on tokio's blocking pool.
+pub struct Ws2812Strip {
+    leds: Arc<Mutex<Ws2812<Spi>>>,
+}
+
+impl Ws2812Strip {
+    pub(crate) fn new() -> Result<Self> {
+        let spi = Spi::new(Bus::Spi0, SlaveSelect::Ss0, 3_000_000, Mode::Mode0)?;
+        Ok(Ws2812Strip {
+            leds: Arc::new(Mutex::new(Ws2812::new(spi))),
+        })
+    }
+}
+
+impl std::fmt::Debug for Ws2812Strip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ws2812")
+    }
+}
+
+#[async_trait]
+impl FrameSink for Ws2812Strip {
+    fn kind(&self) -> &'static str {
+        "ws2812"
+    }
+
+    async fn write_frame(&mut self, frame: &[RGB8]) -> Result<()> {
+        let leds = Arc::clone(&self.leds);
+        let frame = frame.to_vec();
+        tokio::task::spawn_blocking(move || {
+            let mut leds = leds.lock().unwrap_or_else(|e| e.into_inner());
+            leds.write(frame.into_iter())
+        })
+        .await??;
+        Ok(())
+    }
+}
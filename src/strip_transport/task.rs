@@ -0,0 +1,304 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use smart_leds::RGB8;
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::{self, Instant};
+
+use super::{FrameSink, HealthMonitor};
+use crate::Result;
+use async_trait::async_trait;
+
+// bounds the delay queue, about 17s worth of frames at 60 fps
+const DELAY_MAX_FRAMES: usize = 1024;
+
+/// How often a sink's task writes. Frames arriving faster than `max_fps`
+/// are dropped, unchanged frames are skipped unless `keepalive` has passed
+/// since the last write. Every frame is held back by `delay`, to line up
+/// targets with less latency than the slowest one.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Refresh {
+    pub max_fps: Option<f64>,
+    pub keepalive: Option<time::Duration>,
+    pub delay: Option<time::Duration>,
+}
+
+impl Refresh {
+    /// Fields set in `self`, falling back to `defaults` for the rest.
+    pub fn or(self, defaults: Refresh) -> Refresh {
+        Refresh {
+            max_fps: self.max_fps.or(defaults.max_fps),
+            keepalive: self.keepalive.or(defaults.keepalive),
+            delay: self.delay.or(defaults.delay),
+        }
+    }
+}
+
+/// Runs a sink in its own task so it writes at its own pace. Only the latest
+/// frame is kept, anything the sink was too slow to pick up is dropped.
+pub struct SinkTask {
+    kind: &'static str,
+    monitor: Option<HealthMonitor>,
+    refresh: Refresh,
+    sink: Arc<Mutex<Box<dyn FrameSink>>>,
+    frames: Option<watch::Sender<Vec<RGB8>>>,
+    task: Option<JoinHandle<Result<()>>>,
+}
+
+impl SinkTask {
+    pub fn spawn(sink: Box<dyn FrameSink>) -> Self {
+        let mut task = SinkTask {
+            kind: sink.kind(),
+            monitor: sink.monitor(),
+            refresh: sink.refresh(),
+            sink: Arc::new(Mutex::new(sink)),
+            frames: None,
+            task: None,
+        };
+        task.start();
+        task
+    }
+
+    fn start(&mut self) {
+        let (frames, rx) = watch::channel(Vec::new());
+        let sink = Arc::clone(&self.sink);
+        self.task = Some(tokio::spawn(Self::run(sink, rx, self.refresh)));
+        self.frames = Some(frames);
+    }
+
+    /// Writes frames until the sender is dropped, then flushes the frames
+    /// still queued. Frames wait in a queue until their delay has passed and
+    /// only the latest due one is written, so older due frames are dropped as
+    /// new ones arrive. A failed write ends the task, its error is returned by
+    /// the next write which starts the task again.
+    async fn run(
+        sink: Arc<Mutex<Box<dyn FrameSink>>>,
+        mut frames: watch::Receiver<Vec<RGB8>>,
+        refresh: Refresh,
+    ) -> Result<()> {
+        let min_interval = refresh
+            .max_fps
+            .filter(|fps| *fps > 0.0)
+            .map(|fps| time::Duration::from_secs_f64(1.0 / fps));
+        let delay = refresh.delay.unwrap_or_default();
+        let mut queue: VecDeque<(Instant, Vec<RGB8>)> = VecDeque::new();
+        let mut last: Option<(Vec<RGB8>, Instant)> = None;
+        let mut closed = false;
+
+        loop {
+            if closed && queue.is_empty() {
+                break;
+            }
+            let ready_at = match (&last, min_interval) {
+                (Some((_, written)), Some(min_interval)) => *written + min_interval,
+                _ => Instant::now(),
+            };
+            let due_at = queue.front().map(|(at, _)| (*at).max(ready_at));
+            let keepalive_at = match (&last, refresh.keepalive) {
+                (Some((_, written)), Some(keepalive)) if !closed => {
+                    Some((*written + keepalive).max(ready_at))
+                }
+                _ => None,
+            };
+            tokio::select! {
+                changed = frames.changed(), if !closed => match changed {
+                    Ok(()) => {
+                        let now = Instant::now();
+                        while matches!(queue.get(1), Some((at, _)) if *at <= now)
+                            || queue.len() >= DELAY_MAX_FRAMES
+                        {
+                            queue.pop_front();
+                        }
+                        let frame = frames.borrow_and_update().clone();
+                        queue.push_back((now + delay, frame));
+                    }
+                    Err(_) => closed = true,
+                },
+                _ = time::sleep_until(due_at.unwrap_or_else(Instant::now)),
+                    if due_at.is_some() => (),
+                _ = time::sleep_until(keepalive_at.unwrap_or_else(Instant::now)),
+                    if keepalive_at.is_some() => (),
+            }
+
+            let now = Instant::now();
+            if now < ready_at {
+                continue;
+            }
+            let mut due = None;
+            while matches!(queue.front(), Some((at, _)) if *at <= now) {
+                due = queue.pop_front().map(|(_, frame)| frame);
+            }
+            let keepalive_due = matches!(keepalive_at, Some(at) if now >= at);
+            let frame = match (due, &last) {
+                (Some(frame), Some((previous, _))) if frame != *previous || keepalive_due => frame,
+                (Some(frame), None) => frame,
+                (_, Some((previous, _))) if keepalive_due => previous.clone(),
+                _ => continue,
+            };
+
+            sink.lock().await.write_frame(&frame).await?;
+            last = Some((frame, Instant::now()));
+        }
+        Ok(())
+    }
+
+    async fn join(&mut self) -> Result<()> {
+        self.frames = None;
+        match self.task.take() {
+            Some(task) => task.await?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::fmt::Debug for SinkTask {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.sink.try_lock() {
+            Ok(sink) => write!(f, "{:?}", sink),
+            Err(_) => write!(f, "{} (writing)", self.kind),
+        }
+    }
+}
+
+#[async_trait]
+impl FrameSink for SinkTask {
+    fn kind(&self) -> &'static str {
+        self.kind
+    }
+
+    fn spawned(&self) -> bool {
+        true
+    }
+
+    fn monitor(&self) -> Option<HealthMonitor> {
+        self.monitor.clone()
+    }
+
+    async fn write_frame(&mut self, frame: &[RGB8]) -> Result<()> {
+        match &self.frames {
+            Some(frames) if !frames.is_closed() => {
+                frames.send_replace(frame.to_vec());
+                Ok(())
+            }
+            Some(_) => {
+                let failed = self.join().await;
+                self.start();
+                if let Some(frames) = &self.frames {
+                    frames.send_replace(frame.to_vec());
+                }
+                failed
+            }
+            // closed
+            None => Ok(()),
+        }
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        let written = self.join().await;
+        let closed = self.sink.lock().await.close().await;
+        written.and(closed)
+    }
+}
+
+/// A sink with its [`Refresh`] overridden.
+pub(super) struct Refreshed {
+    pub(super) sink: Box<dyn FrameSink>,
+    pub(super) refresh: Refresh,
+}
+
+impl std::fmt::Debug for Refreshed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.sink)
+    }
+}
+
+#[async_trait]
+impl FrameSink for Refreshed {
+    fn kind(&self) -> &'static str {
+        self.sink.kind()
+    }
+
+    fn refresh(&self) -> Refresh {
+        self.refresh.or(self.sink.refresh())
+    }
+
+    fn spawned(&self) -> bool {
+        self.sink.spawned()
+    }
+
+    fn monitor(&self) -> Option<HealthMonitor> {
+        self.sink.monitor()
+    }
+
+    async fn write_frame(&mut self, frame: &[RGB8]) -> Result<()> {
+        self.sink.write_frame(frame).await
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        self.sink.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records when each frame was written, by its first red value.
+    #[derive(Debug)]
+    struct Probe {
+        refresh: Refresh,
+        written: Arc<std::sync::Mutex<Vec<(Instant, u8)>>>,
+    }
+
+    #[async_trait]
+    impl FrameSink for Probe {
+        fn kind(&self) -> &'static str {
+            "probe"
+        }
+
+        fn refresh(&self) -> Refresh {
+            self.refresh
+        }
+
+        async fn write_frame(&mut self, frame: &[RGB8]) -> Result<()> {
+            self.written
+                .lock()
+                .unwrap()
+                .push((Instant::now(), frame[0].r));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn delays_frames_and_flushes_on_close() {
+        let delay = time::Duration::from_millis(100);
+        let written = Arc::default();
+        let mut task = SinkTask::spawn(Box::new(Probe {
+            refresh: Refresh {
+                max_fps: Some(50.0),
+                keepalive: None,
+                delay: Some(delay),
+            },
+            written: Arc::clone(&written),
+        }));
+
+        // input at 60 Hz, faster than the sink's max_fps
+        let start = Instant::now();
+        let mut interval = time::interval(time::Duration::from_secs_f64(1.0 / 60.0));
+        for n in 1..=30 {
+            interval.tick().await;
+            task.write_frame(&[RGB8::new(n, 0, 0)]).await.unwrap();
+        }
+        let last_sent = Instant::now();
+        task.close().await.unwrap();
+
+        let written = written.lock().unwrap();
+        assert!(written.len() > 10, "{:?}", written);
+        assert!(written[0].0 >= start + delay);
+        assert!(written.windows(2).all(|w| w[0].1 < w[1].1));
+        // the final frame is written after its delay rather than dropped
+        assert_eq!(written.last().unwrap().1, 30);
+        assert!(written.last().unwrap().0 >= last_sent + delay - time::Duration::from_millis(20));
+    }
+}
@@ -2,28 +2,126 @@ use std::net::SocketAddr;
 
 use rgb::RGB8;
 use tokio::net::UdpSocket;
+use tokio::time;
 
-use super::AsyncSmartLedsWrite;
+use super::{AsyncSmartLedsWrite, FrameSink, Refresh};
 use crate::{Error, Result};
 use async_trait::async_trait;
 
+const UDP_MAX_PACKET_LEN: usize = 1472;
+// resend well within WLED's realtime timeout so it doesn't revert to effects
+const UDP_KEEPALIVE: time::Duration = time::Duration::from_secs(1);
+
+/// WLED realtime UDP protocols
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UdpMode {
+    Warls,
+    Drgb,
+    Drgbw,
+    Dnrgb,
+}
+
+impl std::str::FromStr for UdpMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "warls" => Ok(UdpMode::Warls),
+            "drgb" => Ok(UdpMode::Drgb),
+            "drgbw" => Ok(UdpMode::Drgbw),
+            "dnrgb" => Ok(UdpMode::Dnrgb),
+            _ => Err(Error::ConfigError(format!("unknown udp mode {}", s))),
+        }
+    }
+}
+
+impl UdpMode {
+    fn protocol(&self) -> u8 {
+        match self {
+            UdpMode::Warls => 1,
+            UdpMode::Drgb => 2,
+            UdpMode::Drgbw => 3,
+            UdpMode::Dnrgb => 4,
+        }
+    }
+
+    fn header_len(&self) -> usize {
+        match self {
+            UdpMode::Dnrgb => 4,
+            _ => 2,
+        }
+    }
+
+    fn led_len(&self) -> usize {
+        match self {
+            UdpMode::Warls | UdpMode::Drgbw => 4,
+            UdpMode::Drgb | UdpMode::Dnrgb => 3,
+        }
+    }
+
+    fn max_leds(&self) -> usize {
+        let leds = (UDP_MAX_PACKET_LEN - self.header_len()) / self.led_len();
+        match self {
+            // WARLS addresses leds with a single byte
+            UdpMode::Warls => leds.min(256),
+            _ => leds,
+        }
+    }
+}
+
 pub struct UdpStrip {
     pub(crate) dest: SocketAddr,
+    pub(crate) mode: UdpMode,
+    timeout: u8,
     sock: UdpSocket,
-    buf: [u8; 2 + 3 * 490],
+    pixels: Vec<RGB8>,
+    buf: [u8; UDP_MAX_PACKET_LEN],
 }
 
 impl UdpStrip {
-    pub(crate) async fn new(dest: std::net::SocketAddr) -> Result<Self> {
+    /// `timeout` is the number of seconds WLED waits before returning to
+    /// its own effects, 255 holds the realtime data indefinitely.
+    pub(crate) async fn new(
+        dest: std::net::SocketAddr,
+        mode: UdpMode,
+        timeout: u8,
+    ) -> Result<Self> {
         let sock = tokio::net::UdpSocket::bind("0.0.0.0:0").await?;
         Ok(UdpStrip {
             sock,
             dest,
-            buf: [u8::default(); 1472],
+            mode,
+            timeout,
+            pixels: Vec::new(),
+            buf: [u8::default(); UDP_MAX_PACKET_LEN],
         })
     }
 }
 
+fn encode(buf: &mut [u8], mode: UdpMode, timeout: u8, start: usize, leds: &[RGB8]) -> usize {
+    buf[0] = mode.protocol();
+    buf[1] = timeout;
+    if mode == UdpMode::Dnrgb {
+        buf[2..4].copy_from_slice(&(start as u16).to_be_bytes());
+    }
+
+    let mut len = mode.header_len();
+    for (idx, led) in leds.iter().enumerate() {
+        let dst = &mut buf[len..len + mode.led_len()];
+        match mode {
+            UdpMode::Warls => dst.copy_from_slice(&[idx as u8, led.r, led.g, led.b]),
+            UdpMode::Drgbw => {
+                let w = led.r.min(led.g).min(led.b);
+                dst.copy_from_slice(&[led.r - w, led.g - w, led.b - w, w])
+            }
+            UdpMode::Drgb | UdpMode::Dnrgb => dst.copy_from_slice(&[led.r, led.g, led.b]),
+        }
+        len += mode.led_len();
+    }
+    len
+}
+
 #[async_trait]
 impl AsyncSmartLedsWrite for UdpStrip {
     type Error = Error;
@@ -34,18 +132,158 @@ impl AsyncSmartLedsWrite for UdpStrip {
         T: Iterator<Item = I> + Send,
         I: Into<Self::Color>,
     {
-        let mut len = 2;
-        self.buf[0] = 2;
-        self.buf[1] = 5;
-        self.buf[2..]
-            .iter_mut()
-            .zip(iterator.flat_map(|item| {
-                len += 3;
-                let i = item.into();
-                [i.r, i.g, i.b]
-            }))
-            .for_each(|(dst, itm)| *dst = itm);
+        self.pixels.clear();
+        self.pixels.extend(iterator.map(|item| item.into()));
+
+        let max_leds = self.mode.max_leds();
+        match self.mode {
+            UdpMode::Dnrgb => {
+                for (n, chunk) in self.pixels.chunks(max_leds).enumerate() {
+                    let len = encode(&mut self.buf, self.mode, self.timeout, n * max_leds, chunk);
+                    self.sock.send_to(&self.buf[..len], self.dest).await?;
+                }
+            }
+            _ => {
+                let leds = &self.pixels[..self.pixels.len().min(max_leds)];
+                let len = encode(&mut self.buf, self.mode, self.timeout, 0, leds);
+                self.sock.send_to(&self.buf[..len], self.dest).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        // a zero timeout hands control straight back to WLED
+        let len = encode(&mut self.buf, self.mode, 0, 0, &[]);
         self.sock.send_to(&self.buf[..len], self.dest).await?;
         Ok(())
     }
 }
+
+impl std::fmt::Debug for UdpStrip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "udp:{:?}/{:?}", self.dest, self.mode)
+    }
+}
+
+#[async_trait]
+impl FrameSink for UdpStrip {
+    fn kind(&self) -> &'static str {
+        "udp"
+    }
+
+    fn refresh(&self) -> Refresh {
+        Refresh {
+            keepalive: Some(UDP_KEEPALIVE),
+            ..Default::default()
+        }
+    }
+
+    async fn write_frame(&mut self, frame: &[RGB8]) -> Result<()> {
+        AsyncSmartLedsWrite::write(self, frame.iter().copied()).await
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        AsyncSmartLedsWrite::close(self).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rgb::RGB8;
+    use tokio::net::UdpSocket;
+
+    use super::*;
+
+    const LEDS: [RGB8; 2] = [
+        RGB8 {
+            r: 10,
+            g: 20,
+            b: 30,
+        },
+        RGB8 {
+            r: 40,
+            g: 50,
+            b: 60,
+        },
+    ];
+
+    fn encoded(mode: UdpMode, start: usize) -> Vec<u8> {
+        let mut buf = [0; UDP_MAX_PACKET_LEN];
+        let len = encode(&mut buf, mode, 5, start, &LEDS);
+        buf[..len].to_vec()
+    }
+
+    #[test]
+    fn encodes_each_mode() {
+        assert_eq!(
+            encoded(UdpMode::Warls, 0),
+            [1, 5, 0, 10, 20, 30, 1, 40, 50, 60]
+        );
+        assert_eq!(encoded(UdpMode::Drgb, 0), [2, 5, 10, 20, 30, 40, 50, 60]);
+        assert_eq!(
+            encoded(UdpMode::Drgbw, 0),
+            [3, 5, 0, 10, 20, 10, 0, 10, 20, 40]
+        );
+        assert_eq!(
+            encoded(UdpMode::Dnrgb, 0x0102),
+            [4, 5, 1, 2, 10, 20, 30, 40, 50, 60]
+        );
+    }
+
+    #[test]
+    fn max_leds_fit_a_packet() {
+        assert_eq!(UdpMode::Warls.max_leds(), 256);
+        assert_eq!(UdpMode::Drgb.max_leds(), 490);
+        assert_eq!(UdpMode::Drgbw.max_leds(), 367);
+        assert_eq!(UdpMode::Dnrgb.max_leds(), 489);
+    }
+
+    #[tokio::test]
+    async fn dnrgb_chunks_with_start_index() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let dest = receiver.local_addr().unwrap();
+        let mut udp = UdpStrip::new(dest, UdpMode::Dnrgb, 255).await.unwrap();
+        let frame = (0..1000)
+            .map(|i| RGB8::new(i as u8, 0, 0))
+            .collect::<Vec<_>>();
+        udp.write_frame(&frame).await.unwrap();
+
+        let mut buf = [0; 2048];
+        for (start, leds) in [(0, 489), (489, 489), (978, 22)] {
+            let len = receiver.recv(&mut buf).await.unwrap();
+            assert_eq!(buf[..4], [4, 255, (start >> 8) as u8, start as u8]);
+            assert_eq!(len, 4 + leds * 3);
+            assert_eq!(buf[4], start as u8);
+        }
+    }
+
+    #[tokio::test]
+    async fn drgb_truncates_to_one_packet() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let dest = receiver.local_addr().unwrap();
+        let mut udp = UdpStrip::new(dest, UdpMode::Drgb, 2).await.unwrap();
+        udp.write_frame(&vec![RGB8::default(); 600]).await.unwrap();
+
+        let mut buf = [0; 2048];
+        let len = receiver.recv(&mut buf).await.unwrap();
+        assert_eq!(buf[..2], [2, 2]);
+        assert_eq!(len, 2 + 490 * 3);
+    }
+
+    #[tokio::test]
+    async fn close_sends_full_header() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let dest = receiver.local_addr().unwrap();
+        let mut buf = [0; 2048];
+        for (mode, header) in [
+            (UdpMode::Drgb, &[2, 0][..]),
+            (UdpMode::Dnrgb, &[4, 0, 0, 0][..]),
+        ] {
+            let mut udp = UdpStrip::new(dest, mode, 255).await.unwrap();
+            FrameSink::close(&mut udp).await.unwrap();
+            let len = receiver.recv(&mut buf).await.unwrap();
+            assert_eq!(&buf[..len], header);
+        }
+    }
+}